@@ -1,15 +1,104 @@
 mod script;
 
 use script::Script;
-use std::{env, io::Error};
+use std::{env, process::ExitCode};
 
-fn main() -> Result<(), Error> {
-    let args: Vec<String> = env::args().collect();
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        println!("usage: ys <file>");
-        Ok(())
-    } else {
-        Script::new(args[1].clone(), None).run()
+        println!("usage: ys <file> [--task <name>] [<arg> | --<key>[=<value>]]...");
+        return ExitCode::SUCCESS;
+    }
+
+    let mut script = Script::new(args[1].clone(), None);
+    script.task = match task(&mut args) {
+        Ok(task) => task,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    script.bind_args(&args[2..]);
+    script.bind_env();
+
+    match script.run() {
+        Ok(code) => exit_code(code),
+        Err(e) => {
+            eprintln!("{e}");
+            e.code.map_or(ExitCode::FAILURE, exit_code)
+        }
+    }
+}
+
+// Exit codes are 0 to 255, so any other code is a failure, rather than wrapping (e.g. 256 to 0).
+fn exit_code(code: i32) -> ExitCode {
+    u8::try_from(code).map_or(ExitCode::FAILURE, ExitCode::from)
+}
+
+// Takes `--task <name>` or `--task=<name>` out of the arguments, which are otherwise the script's.
+fn task(args: &mut Vec<String>) -> Result<Option<String>, String> {
+    let end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+    let Some(i) = args[2..end].iter().position(|a| a == "--task" || a.starts_with("--task="))
+    else {
+        return Ok(None);
+    };
+    let arg = args.remove(2 + i);
+
+    match arg.strip_prefix("--task=") {
+        Some("") => Err("usage: --task=<name>, found no name".into()),
+        Some(name) => Ok(Some(name.into())),
+        None if 2 + i < end - 1 => Ok(Some(args.remove(2 + i))),
+        None => Err("usage: --task <name>, found no name".into()),
+    }
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code() {
+        for e in [
+            (0, ExitCode::SUCCESS),
+            (3, ExitCode::from(3)),
+            (255, ExitCode::from(255)),
+        ] {
+            assert_eq!(e.1, super::exit_code(e.0), "{e:?}");
+        }
+
+        for code in [256, 257, -1] {
+            assert_eq!(ExitCode::FAILURE, super::exit_code(code), "{code}");
+        }
+    }
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn task() {
+        for e in [
+            ("ys a.ys x --task b y", Some("b"), "ys a.ys x y"),
+            ("ys a.ys --task=b", Some("b"), "ys a.ys"),
+            ("ys a.ys x -- --task b", None, "ys a.ys x -- --task b"),
+        ] {
+            let mut list = args(e.0);
+            assert_eq!(e.1.map(String::from), super::task(&mut list).unwrap(), "{e:?}");
+            assert_eq!(args(e.2), list, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn task_no_name() {
+        for e in [
+            ("ys a.ys x --task", "usage: --task <name>, found no name"),
+            ("ys a.ys --task -- x", "usage: --task <name>, found no name"),
+            ("ys a.ys --task=", "usage: --task=<name>, found no name"),
+        ] {
+            assert_eq!(e.1, super::task(&mut args(e.0)).unwrap_err(), "{e:?}");
+        }
     }
 }
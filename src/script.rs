@@ -1,27 +1,77 @@
 mod binding;
 mod r#break;
 mod call;
+mod r#continue;
 mod def;
+mod doc;
 mod each;
 mod echo;
+mod env;
+mod error;
 mod exec;
 mod exit;
+mod flow;
+mod functions;
+mod glob;
+mod header;
 mod r#if;
+mod import;
+mod include;
+mod inline;
+mod job;
+mod kill;
+mod loader;
+mod local;
+mod parallel;
+mod params;
+mod process;
+mod redirect;
+mod r#return;
+mod spawn;
+mod status;
 mod step;
+mod task;
+mod template;
+#[cfg(test)]
+mod test_util;
+mod r#try;
+mod uptodate;
 mod var;
+mod wait;
 mod r#while;
+mod words;
 mod writer;
 
-use binding::Binding;
-use std::fs;
-use std::io::{Error, ErrorKind::Interrupted};
+use binding::{Binding, Frame};
+use error::Location;
+pub use error::{ErrorKind, ScriptError};
+use flow::Flow;
+use job::Job;
+use loader::Marks;
+use std::{fs, path::Path, sync::Arc};
 use writer::Writer;
-use yaml_rust2::{yaml::Array, Yaml, YamlLoader};
+use yaml_rust2::{
+    yaml::{Array, Hash},
+    Yaml,
+};
+
+type EvalFn<T> = fn(&Binding, &Yaml) -> Result<T, ScriptError>;
 
 pub struct Script {
     pub path: String,
     pub binding: Binding,
     pub writer: Writer,
+    // The locations of the steps of the files and procs being run, innermost last (see `loader`).
+    pub marks: Vec<Arc<Marks>>,
+    // Spawned commands, by id, which are killed when the script is dropped if they're still
+    // running.
+    pub jobs: Vec<Job>,
+    // The command-line arguments after the script, for its header.
+    pub args: Vec<String>,
+    // The files being run: the script, then those it includes or imports, innermost last.
+    pub files: Vec<String>,
+    // The document or proc to run (see `doc`), instead of all of the documents.
+    pub task: Option<String>,
 }
 
 impl Script {
@@ -30,36 +80,226 @@ impl Script {
             path,
             binding: Binding::new(),
             writer: Writer::new(log),
+            marks: Vec::new(),
+            jobs: Vec::new(),
+            args: Vec::new(),
+            files: Vec::new(),
+            task: None,
+        }
+    }
+
+    // Binds the command-line arguments after the script: `--<key>=<value>` and `--<key>` (true)
+    // as the `opts` map, and the others, or all of those after `--`, as the `args` list.
+    pub fn bind_args(&mut self, args: &[String]) {
+        let mut list = Array::new();
+        let mut opts = Hash::new();
+        let mut rest = false;
+
+        for arg in args {
+            match arg.strip_prefix("--") {
+                Some("") if !rest => rest = true,
+                Some(opt) if !rest => {
+                    let (key, val) = match opt.split_once('=') {
+                        Some((key, val)) => (key, Yaml::String(val.into())),
+                        None => (opt, Yaml::Boolean(true)),
+                    };
+                    opts.insert(Yaml::String(key.into()), val);
+                }
+                _ => list.push(Yaml::String(arg.clone())),
+            }
         }
+
+        self.binding.set_var("args", Yaml::Array(list));
+        self.binding.set_var("opts", Yaml::Hash(opts));
+        self.args = args.to_vec();
+    }
+
+    // Binds the environment of the process as the `env` map, which is what commands are run with.
+    pub fn bind_env(&mut self) {
+        self.binding.set_var("env", Yaml::Hash(env::process()));
     }
 
     //-------------------------------------------------------------------------
 
-    pub fn run(&mut self) -> Result<(), Error> {
-        match self.run_str(&fs::read_to_string(&self.path).unwrap()) {
-            Err(e) if e.kind() == Interrupted => echo::write(self, e.to_string()),
-            r => r,
+    // Returns the exit code.
+    pub fn run(&mut self) -> Result<i32, ScriptError> {
+        let path = self.path.clone();
+
+        // A break outside of any loop, or a return outside of any proc, ends the script.
+        match self.with_file(&path, |s, text| s.run_str(text))? {
+            Flow::Break {
+                label: None,
+                message,
+            } => echo::write(self, message)?,
+            Flow::Next | Flow::Return(_) => (),
+            Flow::Exit(code) => return Ok(code),
+            flow => return Err(flow.error()),
         }
+
+        Ok(0)
+    }
+
+    fn run_str(&mut self, text: &str) -> Result<Flow, ScriptError> {
+        self.with_docs(text, |s, docs| s.run_docs(docs))
     }
 
-    fn run_str(&mut self, text: &str) -> Result<(), Error> {
-        self.run_docs(YamlLoader::load_from_str(text).unwrap())
+    // Loads the documents of the current file, and runs `f` on them, with where each of them
+    // starts. The locations of their steps are kept until it returns.
+    fn with_docs<T, F>(&mut self, text: &str, f: F) -> Result<T, ScriptError>
+    where
+        F: FnOnce(&mut Self, &[(Yaml, Option<Location>)]) -> Result<T, ScriptError>,
+    {
+        let (docs, marks) = loader::load(self.file(), text)?;
+        self.marks.push(Arc::new(marks));
+        let res = f(self, &docs);
+        self.marks.pop();
+
+        res
     }
 
-    fn run_docs(&mut self, docs: Vec<Yaml>) -> Result<(), Error> {
-        for doc in docs {
-            self.run_steps(doc.as_vec().expect("expected list"))?;
+    fn run_docs(&mut self, docs: &[(Yaml, Option<Location>)]) -> Result<Flow, ScriptError> {
+        let (header, docs) = match docs.split_first() {
+            Some(((Yaml::Hash(header), location), rest)) if doc::is_header(&docs[0].0) => {
+                (Some((header, location)), rest)
+            }
+            _ => (None, docs),
+        };
+
+        let docs = docs
+            .iter()
+            .map(|(d, location)| doc::parse(d).map_err(|e| e.at(location.as_ref())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Only the script itself takes options and a task, so they don't apply to an included
+        // file, and its header is ignored.
+        if self.files.len() > 1 {
+            return doc::run(self, &docs, None);
         }
 
-        Ok(())
+        if let Some((header, location)) = header {
+            let args = self.args.clone();
+            let flow =
+                header::run(self, header, &docs, &args).map_err(|e| e.at(location.as_ref()))?;
+
+            if flow != Flow::Next {
+                return Ok(flow);
+            }
+        }
+
+        // Without a task option, the leading arguments that are the names of tasks are the tasks
+        // to run after the script, or else the task named `default`, if there is one. The others
+        // are only arguments, e.g. `ys make.ys build test x` runs build and test.
+        let args = self.binding.var("args");
+        let task = self.task.clone();
+        let flow = doc::run(self, &docs, task.as_deref())?;
+
+        if flow != Flow::Next || task.is_some() || !task::any(self) {
+            return Ok(flow);
+        }
+
+        let mut names: Vec<String> = args
+            .as_vec()
+            .into_iter()
+            .flatten()
+            .map_while(|a| a.as_str().filter(|a| task::is_task(self, a)))
+            .map(String::from)
+            .collect();
+
+        if names.is_empty() && task::is_task(self, "default") {
+            names.push("default".into());
+        }
+
+        task::run_tasks(self, &names)
     }
 
-    fn run_steps(&mut self, steps: &Array) -> Result<(), Error> {
+    // Runs steps until one of them breaks out of them.
+    fn run_steps(&mut self, steps: &[Yaml]) -> Result<Flow, ScriptError> {
         for step in steps {
-            step::run(self, step.as_hash().expect("expected mapping"))?;
+            let hash =
+                step.as_hash().ok_or_else(|| ScriptError::type_mismatch("mapping", "step"))?;
+
+            match step::run(self, hash).map_err(|e| e.at(loader::location(&self.marks, hash)))? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    //-------------------------------------------------------------------------
+
+    // The file being run, which paths are relative to.
+    pub fn file(&self) -> &str {
+        self.files.last().unwrap_or(&self.path)
+    }
+
+    // Resolves a path relative to the directory of the current file.
+    pub fn resolve(&self, path: &str) -> String {
+        let dir = Path::new(self.file()).parent().unwrap_or(Path::new(""));
+        dir.join(path).display().to_string()
+    }
+
+    // Reads a file and runs `f` on its text, as the current file. It's an error if the file is
+    // already being run, e.g. if it includes itself.
+    fn with_file<T, F>(&mut self, path: &str, f: F) -> Result<T, ScriptError>
+    where
+        F: FnOnce(&mut Self, &str) -> Result<T, ScriptError>,
+    {
+        let io_error = |e| ScriptError::new(ErrorKind::Io, format!("{path}: {e}"));
+        let text = fs::read_to_string(path).map_err(io_error)?;
+        let canonical = fs::canonicalize(path).map_err(io_error)?;
+
+        if self.files.iter().any(|f| fs::canonicalize(f).is_ok_and(|f| f == canonical)) {
+            let cycle = [self.files.as_slice(), &[path.into()]].concat().join(" -> ");
+            return Err(ScriptError::new(ErrorKind::Flow, format!("cycle: {cycle}")));
         }
 
-        Ok(())
+        self.files.push(path.into());
+        let res = f(self, &text);
+        self.files.pop();
+
+        res
+    }
+
+    //-------------------------------------------------------------------------
+    // Values are evaluated through the script, so that procs called in expressions can be run.
+
+    pub fn eval_to_yaml(&mut self, yaml: &Yaml) -> Result<Yaml, ScriptError> {
+        self.eval_with(yaml, Binding::eval_to_yaml)
+    }
+
+    pub fn eval_to_string(&mut self, yaml: &Yaml) -> Result<String, ScriptError> {
+        self.eval_with(yaml, Binding::eval_to_string)
+    }
+
+    pub fn eval_to_i32(&mut self, yaml: &Yaml) -> Result<i32, ScriptError> {
+        self.eval_with(yaml, Binding::eval_to_i32)
+    }
+
+    pub fn is_truthy(&mut self, cond: &Yaml) -> Result<bool, ScriptError> {
+        self.eval_with(cond, Binding::is_truthy)
+    }
+
+    // Evaluates each entry of a mapping in the current scope, for a new frame.
+    pub fn eval_to_frame(&mut self, hash: &Hash, key: &str) -> Result<Frame, ScriptError> {
+        let mut frame = Frame::new();
+
+        for (name, yaml) in hash {
+            let val = self.eval_to_yaml(yaml)?;
+            frame.insert(Binding::yaml_to_name(name, key)?.into(), val);
+        }
+
+        Ok(frame)
+    }
+
+    fn eval_with<T>(&mut self, yaml: &Yaml, eval: EvalFn<T>) -> Result<T, ScriptError> {
+        let (yaml, calls) = inline::resolve(self, yaml)?;
+        self.binding.calls = calls;
+        let res = eval(&self.binding, &yaml);
+        self.binding.calls.clear();
+
+        res
     }
 }
 
@@ -68,7 +308,7 @@ impl Script {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::ErrorKind::Interrupted;
+    use yaml_rust2::YamlLoader;
 
     #[test]
     fn run_steps() {
@@ -76,35 +316,195 @@ mod tests {
         let docs = YamlLoader::load_from_str("[a: 42, echo: foo]").unwrap();
         let steps = docs[0].as_vec().unwrap();
 
-        _ = script.run_steps(&steps);
+        _ = script.run_steps(steps);
         assert_eq!(42, script.binding.var("a").as_i64().unwrap());
         assert_eq!("foo", script.writer.log[0]);
     }
 
     //-------------------------------------------------------------------------
 
+    #[test]
+    fn bind_args() {
+        let mut script = Script::new(String::new(), None);
+        let args = ["a", "--n=1", "--v", "--x=", "b", "--", "--c"].map(String::from);
+
+        script.bind_args(&args);
+        let list = script.binding.var("args");
+        assert_eq!(
+            vec!["a", "b", "--c"],
+            list.as_vec().unwrap().iter().map(|a| a.as_str().unwrap()).collect::<Vec<_>>()
+        );
+
+        let opts = script.binding.var("opts");
+        assert_eq!("1", opts["n"].as_str().unwrap());
+        assert!(opts["v"].as_bool().unwrap());
+        assert_eq!("", opts["x"].as_str().unwrap());
+    }
+
+    #[test]
+    fn bind_env() {
+        let mut script = Script::new(String::new(), None);
+
+        script.bind_env();
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(path, script.binding.eval_to_string(&Yaml::from_str("${env.PATH}")).unwrap());
+    }
+
+    //-------------------------------------------------------------------------
+
+    fn docs(text: &str) -> Vec<(Yaml, Option<Location>)> {
+        YamlLoader::load_from_str(text).unwrap().into_iter().map(|d| (d, None)).collect()
+    }
+
     #[test]
     fn run_docs_while_break() {
         let mut script = Script::new(String::new(), None);
-        let docs = YamlLoader::load_from_str("[{while: true, do: [break: true]}]").unwrap();
+        let docs = docs("[{while: true, do: [break: true]}]");
 
-        script.run_docs(docs).unwrap();
+        script.run_docs(&docs).unwrap();
     }
 
     #[test]
     fn run_docs_each_break() {
         let mut script = Script::new(String::new(), None);
-        let docs = YamlLoader::load_from_str("[{each: x, in: [1, 2], do: [break: true]}]").unwrap();
+        let docs = docs("[{each: x, in: [1, 2], do: [break: true]}]");
 
-        script.run_docs(docs).unwrap();
+        script.run_docs(&docs).unwrap();
     }
 
     #[test]
     fn run_docs_break() {
         let mut script = Script::new(String::new(), Some(Vec::new()));
-        let docs = YamlLoader::load_from_str("[break: true]").unwrap();
+        let docs = docs("[break: true]");
+
+        let flow = script.run_docs(&docs).unwrap();
+        assert_eq!(
+            Flow::Break {
+                label: None,
+                message: "(break)".into()
+            },
+            flow
+        );
+    }
+
+    #[test]
+    fn run_docs_continue() {
+        let mut script = Script::new(String::new(), None);
+        let docs = docs("[continue: true, a: 1]");
+
+        assert_eq!(Flow::Continue { label: None }, script.run_docs(&docs).unwrap());
+        assert_eq!(Yaml::Null, script.binding.var("a"));
+    }
+
+    #[test]
+    fn run_docs_exit() {
+        let mut script = Script::new(String::new(), None);
+        let docs = docs("[{while: true, do: [exit: 3]}, a: 1]");
+
+        assert_eq!(Flow::Exit(3), script.run_docs(&docs).unwrap());
+        assert_eq!(Yaml::Null, script.binding.var("a"));
+    }
+
+    //-------------------------------------------------------------------------
+
+    #[test]
+    fn run_str_location() {
+        let mut script = Script::new("foo.ys".into(), None);
+
+        let err = script.run_str("- echo: foo\n- if: true\n  then: bar\n").unwrap_err();
+        assert_eq!(ErrorKind::TypeMismatch, err.kind);
+        assert_eq!("foo.ys:2:3: expected list for 'then'", err.to_string());
+    }
+
+    #[test]
+    fn run_str_location_identical() {
+        let mut script = Script::new("foo.ys".into(), Some(Vec::new()));
+        let text = "- n: 1\n- echo: ${10 - n}\n- n: null\n- echo: ${10 - n}\n";
+
+        let err = script.run_str(text).unwrap_err();
+        assert_eq!(vec!["9"], script.writer.log);
+        assert_eq!(Some(4), err.location.map(|l| l.line));
+    }
+
+    #[test]
+    fn run_str_not_header() {
+        for e in [
+            ("echo: hi\n", "foo.ys:1:1: expected 'name'"),
+            ("name: build\nsteps: [echo: hi]\n", "foo.ys:1:1: unknown key 'steps'"),
+        ] {
+            let mut script = Script::new("foo.ys".into(), Some(Vec::new()));
+            assert_eq!(e.1, script.run_str(e.0).unwrap_err().to_string(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn run_str_header() {
+        let mut script = Script::new("foo.ys".into(), Some(Vec::new()));
+        let text = "params: [{name: a, type: integer}]\n---\n- echo: ${a + 1}\n";
+
+        script.bind_args(&["--a=1".into()]);
+        script.run_str(text).unwrap();
+        assert_eq!("2", script.writer.log[0]);
+
+        script.bind_args(&[]);
+        let err = script.run_str(text).unwrap_err();
+        assert_eq!("foo.ys:1:1: script: missing argument 'a'", err.to_string());
+    }
+
+    #[test]
+    fn run_str_tasks() {
+        let text = "[{task: a, do: [echo: a]}, {task: default, needs: [a], do: [echo: b]}]";
+
+        for (args, log) in [
+            (vec![], vec!["a", "b"]),
+            (vec!["a", "a"], vec!["a"]),
+            (vec!["default", "x", "a"], vec!["a", "b"]),
+            (vec!["x", "a"], vec!["a", "b"]),
+            (vec!["a", "x", "default"], vec!["a"]),
+        ] {
+            let mut script = Script::new(String::new(), Some(Vec::new()));
+            script.bind_args(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+
+            script.run_str(text).unwrap();
+            assert_eq!(log, script.writer.log, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn run_str_nested_location() {
+        let mut script = Script::new("foo.ys".into(), None);
+
+        let err = script.run_str("- while: true\n  do:\n    - echo: ${a +}\n").unwrap_err();
+        assert_eq!(ErrorKind::Expression, err.kind);
+        assert_eq!("foo.ys", err.location.as_ref().unwrap().path);
+        assert_eq!(3, err.location.unwrap().line);
+    }
+
+    #[test]
+    fn run_str_proc_location() {
+        let mut script = Script::new("foo.ys".into(), None);
+
+        script.run_str("- def: f\n  do:\n    - echo: ${a +}\n").unwrap();
+        assert!(script.marks.is_empty());
+
+        let err = script.run_str("- parallel: [call: f]\n").unwrap_err();
+        assert_eq!("foo.ys:3:7", err.location.unwrap().to_string());
+        assert!(script.marks.is_empty());
+    }
+
+    #[test]
+    fn run_str_parse_error() {
+        let mut script = Script::new("foo.ys".into(), None);
+
+        let err = script.run_str("- echo: [foo").unwrap_err();
+        assert_eq!(ErrorKind::Parse, err.kind);
+    }
+
+    #[test]
+    fn run_missing_file() {
+        let mut script = Script::new("missing.ys".into(), None);
 
-        let err = script.run_docs(docs).unwrap_err();
-        assert_eq!(Interrupted, err.kind());
+        let err = script.run().unwrap_err();
+        assert_eq!(ErrorKind::Io, err.kind);
     }
 }
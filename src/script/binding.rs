@@ -1,38 +1,73 @@
+use super::error::{ErrorKind, ScriptError};
+use super::loader::Marks;
+use super::{functions, template};
 use eval::{Expr, Value};
-use regex::{Match, Regex};
 use serde_json::Number;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 use yaml_rust2::{
     yaml::{Array, Hash},
-    Yaml, YamlLoader,
+    Yaml,
 };
 
+pub type Frame = HashMap<String, Yaml>;
+
+// A def or task step, with the file that defined it, which its paths are relative to, and the
+// locations of its steps (see `loader::copy`).
+pub struct Proc {
+    pub def: Hash,
+    pub file: String,
+    pub marks: Arc<Marks>,
+}
+
+// Variables are looked up in the current frame (i.e. the innermost call), then in the globals.
+#[derive(Clone)]
 pub struct Binding {
-    pub vars: HashMap<String, Yaml>,
-    pub procs: HashMap<String, Yaml>,
-    pub params: HashMap<String, Yaml>,
+    pub vars: Frame,
+    pub procs: HashMap<String, Arc<Proc>>,
+    pub frames: Vec<Frame>,
+    // Results of procs called in the expression being evaluated (see `inline`).
+    pub calls: Frame,
+    // Namespaces of the procs being called (see `import`), innermost last.
+    pub namespaces: Vec<String>,
 }
 
 impl Binding {
     pub fn new() -> Self {
         Self {
-            vars: HashMap::new(),
+            vars: Frame::new(),
             procs: HashMap::new(),
-            params: HashMap::new(),
+            frames: Vec::new(),
+            calls: Frame::new(),
+            namespaces: Vec::new(),
         }
     }
 
-    pub fn entry_to_list(hash: &Hash, key: &str) -> Array {
-        hash.get(&Yaml::from_str(key))
-            .expect(&format!("expected '${key}'"))
-            .clone()
-            .into_vec()
-            .expect("expected list")
+    pub fn entry_to_list<'a>(hash: &'a Hash, key: &str) -> Result<&'a [Yaml], ScriptError> {
+        let yaml = hash.get(&Yaml::from_str(key)).ok_or_else(|| ScriptError::missing_key(key))?;
+        yaml.as_vec().map(Vec::as_slice).ok_or_else(|| ScriptError::type_mismatch("list", key))
     }
 
-    #[allow(dead_code)]
+    pub fn entry_to_list_or_empty<'a>(
+        hash: &'a Hash,
+        key: &str,
+    ) -> Result<&'a [Yaml], ScriptError> {
+        match hash.contains_key(&Yaml::from_str(key)) {
+            true => Self::entry_to_list(hash, key),
+            false => Ok(&[]),
+        }
+    }
+
+    pub fn entry_to_name<'a>(hash: &'a Hash, key: &str) -> Result<Option<&'a str>, ScriptError> {
+        hash.get(&Yaml::from_str(key)).map(|e| Self::yaml_to_name(e, key)).transpose()
+    }
+
+    pub fn yaml_to_name<'a>(yaml: &'a Yaml, key: &str) -> Result<&'a str, ScriptError> {
+        yaml.as_str().ok_or_else(|| ScriptError::type_mismatch("name", key))
+    }
+
+    #[cfg(test)]
     pub fn hash_from_str(text: &str) -> Hash {
-        YamlLoader::load_from_str(text).unwrap()[0].as_hash().unwrap().clone()
+        yaml_rust2::YamlLoader::load_from_str(text).unwrap()[0].as_hash().unwrap().clone()
     }
 
     //-------------------------------------------------------------------------
@@ -41,8 +76,9 @@ impl Binding {
     pub fn var<S: Into<String>>(&self, name: S) -> Yaml {
         let key = &name.into();
 
-        self.params
-            .get(key)
+        self.frames
+            .last()
+            .and_then(|frame| frame.get(key))
             .or_else(|| -> Option<&Yaml> { self.vars.get(key) })
             .unwrap_or(&Yaml::Null)
             .clone()
@@ -51,101 +87,145 @@ impl Binding {
     pub fn set_var<S: Into<String>>(&mut self, name: S, val: Yaml) {
         let key = name.into();
 
-        match self.params.get(&key) {
-            Some(_) => self.set_param(key, val),
-            None => _ = self.vars.insert(key, val),
+        match self.frames.last_mut() {
+            Some(frame) if frame.contains_key(&key) => _ = frame.insert(key, val),
+            _ => _ = self.vars.insert(key, val),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn param<S: Into<String>>(&mut self, name: S) -> Yaml {
-        self.params.get(&name.into()).unwrap_or(&Yaml::Null).clone()
+    pub fn set_local<S: Into<String>>(&mut self, name: S, val: Yaml) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.insert(name.into(), val),
+            None => self.vars.insert(name.into(), val),
+        };
     }
 
-    pub fn set_param<S: Into<String>>(&mut self, name: S, val: Yaml) {
-        self.params.insert(name.into(), val);
+    pub fn push_frame(&mut self, frame: Frame) {
+        self.frames.push(frame);
     }
 
-    pub fn set_params(&mut self, hash: Hash) -> HashMap<String, Yaml> {
-        let old = self.params.clone();
+    pub fn pop_frame(&mut self) -> Option<Frame> {
+        self.frames.pop()
+    }
 
-        for (param, yaml) in hash {
-            self.set_param(param.as_str().unwrap(), self.eval_to_yaml(&yaml));
-        }
+    pub fn proc<S: Into<String>>(&self, name: S) -> Result<Arc<Proc>, ScriptError> {
+        let key = name.into();
 
-        old
+        match self.proc_name(&key) {
+            Some(name) => Ok(self.procs[&name].clone()),
+            None => Err(ScriptError::new(ErrorKind::UnknownStep, format!("unknown proc '{key}'"))),
+        }
     }
 
-    pub fn proc<S: Into<String>>(&self, name: S) -> Yaml {
-        self.procs.get(&name.into()).unwrap().clone()
+    // The full name of a proc: in the namespace of the calling proc, if it's defined there, so
+    // that the procs of an imported file can call each other, or else as it is.
+    pub fn proc_name(&self, name: &str) -> Option<String> {
+        let namespaced =
+            self.namespaces.last().filter(|ns| !ns.is_empty()).map(|ns| format!("{ns}.{name}"));
+        namespaced.into_iter().chain([name.to_string()]).find(|n| self.procs.contains_key(n))
     }
 
-    pub fn set_proc<S: Into<String>>(&mut self, name: S, val: Yaml) {
-        self.procs.insert(name.into(), val);
+    pub fn set_proc<S: Into<String>>(&mut self, name: S, proc: Proc) {
+        self.procs.insert(name.into(), Arc::new(proc));
     }
 
     //-------------------------------------------------------------------------
 
-    pub fn is_truthy(&self, cond: &Yaml) -> bool {
-        match self.eval_to_yaml(cond) {
+    pub fn is_truthy(&self, cond: &Yaml) -> Result<bool, ScriptError> {
+        Ok(match self.eval_to_yaml(cond)? {
             Yaml::Boolean(b) => b,
-            Yaml::Real(s) => s.parse::<f64>().unwrap() != 0.0f64,
+            Yaml::Real(s) => s.parse::<f64>().unwrap_or(0.0f64) != 0.0f64,
             Yaml::Integer(n) => n != 0i64,
             Yaml::String(s) => !s.is_empty(),
+            Yaml::Array(list) => !list.is_empty(),
+            Yaml::Hash(hash) => !hash.is_empty(),
             // ???: more?
             _ => false,
-        }
+        })
     }
 
     //-------------------------------------------------------------------------
 
-    pub fn eval_to_string(&self, yaml: &Yaml) -> String {
-        self.value_to_string(self.eval(yaml))
+    pub fn eval_to_string(&self, yaml: &Yaml) -> Result<String, ScriptError> {
+        Ok(self.value_to_string(self.eval(yaml)?))
     }
 
-    pub fn eval_to_i32(&self, yaml: &Yaml) -> i32 {
-        self.value_to_i32(self.eval(yaml))
+    pub fn eval_to_i32(&self, yaml: &Yaml) -> Result<i32, ScriptError> {
+        self.value_to_i32(self.eval(yaml)?)
     }
 
-    pub fn eval_to_yaml(&self, yaml: &Yaml) -> Yaml {
-        self.value_to_yaml(self.eval(yaml))
+    pub fn eval_to_yaml(&self, yaml: &Yaml) -> Result<Yaml, ScriptError> {
+        // Lists and maps are evaluated element by element. (Maps also keep their order when they're
+        // the values of expressions, as serde_json's maps are ordered, with `preserve_order`.)
+        match yaml {
+            Yaml::Array(list) => {
+                let items: Result<Array, ScriptError> =
+                    list.iter().map(|e| self.eval_to_yaml(e)).collect();
+                Ok(Yaml::Array(items?))
+            }
+            Yaml::Hash(hash) => {
+                let entries: Result<Hash, ScriptError> =
+                    hash.iter().map(|(k, v)| Ok((k.clone(), self.eval_to_yaml(v)?))).collect();
+                Ok(Yaml::Hash(entries?))
+            }
+            _ => Ok(self.value_to_yaml(self.eval(yaml)?)),
+        }
     }
 
-    pub fn eval(&self, yaml: &Yaml) -> Value {
-        match self.yaml_to_value(yaml) {
-            Value::String(s) => self.eval_expr(s),
-            v => v,
+    pub fn eval(&self, yaml: &Yaml) -> Result<Value, ScriptError> {
+        match yaml {
+            Yaml::Array(_) | Yaml::Hash(_) => Ok(self.yaml_to_value(&self.eval_to_yaml(yaml)?)),
+            Yaml::String(s) => self.eval_expr(s.clone()),
+            _ => Ok(self.yaml_to_value(yaml)),
         }
     }
 
-    fn eval_expr(&self, expr: String) -> Value {
-        let re = Regex::new(r"\$\{[a-zA-Z0-9_\.+\-\*/%=<>!&| ]*\}").unwrap();
+    // Evaluates the expressions in a text (see `template`), and interpolates their values.
+    fn eval_expr(&self, text: String) -> Result<Value, ScriptError> {
+        let ranges = template::expressions(&text)?;
 
-        match re.is_match(&expr) {
-            true => self.eval_tokens(expr, re),
-            false => Value::String(expr.into()),
+        // A lone expression keeps its value (e.g. a list, or a string that looks like a number)
+        // instead of being interpolated into a string.
+        match ranges.as_slice() {
+            [] => return Ok(Value::String(template::unescape(&text))),
+            [range] if range.len() == text.len() => return self.eval_token(&text),
+            _ => (),
         }
-    }
 
-    fn eval_tokens(&self, expr: String, re: Regex) -> Value {
-        let mut buf = expr.clone();
+        let mut buf = String::new();
+        let mut last = 0;
 
-        while let Some(m) = re.find(&buf) {
-            buf.replace_range(m.start()..m.end(), &self.eval_token(m));
+        for range in ranges {
+            buf.push_str(&template::unescape(&text[last..range.start]));
+            buf.push_str(&self.value_to_string(self.eval_token(&text[range.clone()])?));
+            last = range.end;
         }
 
-        self.yaml_to_value(&Yaml::from_str(&buf))
+        buf.push_str(&template::unescape(&text[last..]));
+        Ok(self.yaml_to_value(&Yaml::from_str(&buf)))
     }
 
-    fn eval_token(&self, token: Match<'_>) -> String {
-        let mut expr = Expr::new(token.as_str().replace("${", "").replace("}", ""));
+    fn eval_token(&self, token: &str) -> Result<Value, ScriptError> {
+        let (text, strings) = template::literals(&token[2..token.len() - 1]);
+        let mut expr = Expr::new(text);
+        expr = functions::add(expr);
+
+        for (name, val) in strings {
+            expr = expr.value(name, val);
+        }
+
         expr = self.add_values(expr, &self.vars);
-        expr = self.add_values(expr, &self.params);
 
-        self.value_to_string(expr.exec().unwrap())
+        if let Some(frame) = self.frames.last() {
+            expr = self.add_values(expr, frame);
+        }
+
+        expr = self.add_values(expr, &self.calls);
+
+        expr.exec().map_err(|e| ScriptError::new(ErrorKind::Expression, format!("{token}: {e}")))
     }
 
-    fn add_values(&self, mut expr: Expr, vars: &HashMap<String, Yaml>) -> Expr {
+    fn add_values(&self, mut expr: Expr, vars: &Frame) -> Expr {
         for (name, yaml) in vars {
             expr = expr.value(name, self.yaml_to_value(yaml));
         }
@@ -162,8 +242,16 @@ impl Binding {
         }
     }
 
-    pub fn value_to_i32(&self, val: Value) -> i32 {
-        val.as_i64().expect("expected number").try_into().unwrap_or(0)
+    pub fn value_to_i32(&self, val: Value) -> Result<i32, ScriptError> {
+        let msg = match val.as_i64() {
+            Some(n) => match n.try_into() {
+                Ok(n) => return Ok(n),
+                Err(_) => format!("number out of range: {n}"),
+            },
+            None => format!("expected number: {val}"),
+        };
+
+        Err(ScriptError::new(ErrorKind::TypeMismatch, msg))
     }
 
     pub fn yaml_to_value(&self, yaml: &Yaml) -> Value {
@@ -171,21 +259,44 @@ impl Binding {
             Yaml::Boolean(b) => Value::Bool(*b),
             Yaml::Integer(i) => Value::Number((*i).into()),
             Yaml::Null => Value::Null,
-            Yaml::Real(_) => Value::Number(Number::from_f64(yaml.as_f64().unwrap()).unwrap()),
+            // JSON has no NaN or infinity, so they're kept as they're written, e.g. `.nan`.
+            Yaml::Real(s) => yaml
+                .as_f64()
+                .and_then(Number::from_f64)
+                .map_or(Value::String(s.clone()), Value::Number),
             Yaml::String(s) => Value::String(s.into()),
+            Yaml::Array(list) => Value::Array(list.iter().map(|e| self.yaml_to_value(e)).collect()),
+            Yaml::Hash(hash) => Value::Object(
+                hash.iter().map(|(k, v)| (self.key_to_string(k), self.yaml_to_value(v))).collect(),
+            ),
             // ...
             _ => Value::String(format!("{yaml:?}")),
         }
     }
 
+    fn key_to_string(&self, key: &Yaml) -> String {
+        match key {
+            Yaml::String(s) | Yaml::Real(s) => s.clone(),
+            Yaml::Integer(n) => n.to_string(),
+            Yaml::Boolean(b) => b.to_string(),
+            _ => format!("{key:?}"),
+        }
+    }
+
     pub fn value_to_yaml(&self, val: Value) -> Yaml {
         match val {
-            Value::Array(_) => todo!(),
+            Value::Array(list) => {
+                Yaml::Array(list.into_iter().map(|e| self.value_to_yaml(e)).collect())
+            }
             Value::Bool(b) => Yaml::Boolean(b),
             Value::Null => Yaml::Null,
-            Value::Number(n) if n.is_f64() => Yaml::Real(n.to_string()),
-            Value::Number(n) => Yaml::Integer(n.as_i64().unwrap()),
-            Value::Object(_) => todo!(),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Yaml::Integer(i),
+                None => Yaml::Real(n.to_string()),
+            },
+            Value::Object(map) => Yaml::Hash(
+                map.into_iter().map(|(k, v)| (Yaml::String(k), self.value_to_yaml(v))).collect(),
+            ),
             Value::String(s) => Yaml::String(s),
         }
     }
@@ -207,13 +318,42 @@ mod tests {
     }
 
     #[test]
-    fn set_var_param() {
+    fn set_var_local() {
         let mut binding = Binding::new();
-        binding.set_param("a", Yaml::Integer(1));
+        binding.push_frame(Frame::new());
+        binding.set_local("a", Yaml::Integer(1));
 
-        binding.set_var("a", Yaml::Integer(1));
-        assert_eq!(1, binding.param("a").as_i64().unwrap());
+        binding.set_var("a", Yaml::Integer(2));
+        binding.set_var("b", Yaml::Integer(3));
+        assert_eq!(2, binding.var("a").as_i64().unwrap());
         assert_eq!(None, binding.vars.get("a"));
+        assert_eq!(3, binding.vars.get("b").unwrap().as_i64().unwrap());
+    }
+
+    #[test]
+    fn set_local_global() {
+        let mut binding = Binding::new();
+
+        binding.set_local("a", Yaml::Integer(1));
+        assert_eq!(1, binding.vars.get("a").unwrap().as_i64().unwrap());
+    }
+
+    #[test]
+    fn var_frames() {
+        let mut binding = Binding::new();
+        binding.set_var("a", Yaml::Integer(1));
+        binding.set_var("b", Yaml::Integer(1));
+        binding.push_frame(Frame::from([("a".into(), Yaml::Integer(2))]));
+        binding.push_frame(Frame::from([("c".into(), Yaml::Integer(3))]));
+
+        // Only the innermost frame is visible.
+        assert_eq!(Yaml::Integer(1), binding.var("a"));
+        assert_eq!(Yaml::Integer(1), binding.var("b"));
+        assert_eq!(Yaml::Integer(3), binding.var("c"));
+
+        binding.pop_frame();
+        assert_eq!(Yaml::Integer(2), binding.var("a"));
+        assert_eq!(Yaml::Null, binding.var("c"));
     }
 
     #[test]
@@ -222,7 +362,7 @@ mod tests {
         binding.set_var("a", Yaml::Integer(1));
         binding.set_var("b", Yaml::Integer(2));
 
-        for e in vec![
+        for e in [
             ("0", Value::from(0)),
             ("1.0", Value::from(1.0f64)),
             ("true", Value::from(true)),
@@ -233,23 +373,152 @@ mod tests {
             ("${a == 1}", Value::from(true)),
             // ...
         ] {
-            assert_eq!(e.1, binding.eval(&Yaml::from_str(e.0)), "{e:?}");
+            assert_eq!(e.1, binding.eval(&Yaml::from_str(e.0)).unwrap(), "{e:?}");
         }
     }
 
+    #[test]
+    fn eval_string() {
+        let mut binding = Binding::new();
+        binding.set_var("t", Yaml::String("007".into()));
+
+        for e in [
+            ("${t}", Yaml::String("007".into())),
+            ("${'123'}", Yaml::String("123".into())),
+            ("${'true'}", Yaml::String("true".into())),
+            ("${t + '1'}", Yaml::String("0071".into())),
+        ] {
+            assert_eq!(e.1, binding.eval_to_yaml(&Yaml::from_str(e.0)).unwrap(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn eval_non_finite() {
+        let mut binding = Binding::new();
+        binding.set_var("cfg", Yaml::Hash(Binding::hash_from_str("{a: .nan, b: -.inf}")));
+
+        for e in [
+            ("${cfg.a}", Value::from(".nan")),
+            ("${cfg.b}", Value::from("-.inf")),
+            ("${len(cfg)}", Value::from(2)),
+        ] {
+            assert_eq!(e.1, binding.eval(&Yaml::from_str(e.0)).unwrap(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn eval_list_map() {
+        let mut binding = Binding::new();
+        let hash = Binding::hash_from_str("{items: [1, 2], cfg: {host: foo, port: 80}}");
+        binding.set_var("items", hash[&Yaml::from_str("items")].clone());
+        binding.set_var("cfg", hash[&Yaml::from_str("cfg")].clone());
+
+        for e in [
+            ("${items}", Value::from(vec![1, 2])),
+            ("${items[1]}", Value::from(2)),
+            ("${len(items)}", Value::from(2)),
+            ("${cfg.host}", Value::from("foo")),
+            ("${cfg.port + 1}", Value::from(81)),
+            ("items: ${items}", Value::from("items: [1,2]")),
+        ] {
+            assert_eq!(e.1, binding.eval(&Yaml::from_str(e.0)).unwrap(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn eval_to_yaml_list_map() {
+        let mut binding = Binding::new();
+        binding.set_var("a", Yaml::Integer(1));
+        let hash = Binding::hash_from_str("{list: ['${a}', 2], map: {b: '${a + 1}', a: x}}");
+
+        let yaml = binding.eval_to_yaml(&Yaml::Hash(hash)).unwrap();
+        assert_eq!(
+            Binding::hash_from_str("{list: [1, 2], map: {b: 2, a: x}}"),
+            yaml.into_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn eval_map_order() {
+        let mut binding = Binding::new();
+        let hash = Binding::hash_from_str("{zeta: 1, alpha: 2, mid: {b: 1, a: 2}}");
+        binding.set_var("cfg", Yaml::Hash(hash.clone()));
+
+        assert_eq!(Yaml::Hash(hash), binding.eval_to_yaml(&Yaml::from_str("${cfg}")).unwrap());
+        assert_eq!(
+            Value::from(r#"cfg: {"zeta":1,"alpha":2,"mid":{"b":1,"a":2}}"#),
+            binding.eval(&Yaml::from_str("cfg: ${cfg}")).unwrap()
+        );
+    }
+
+    #[test]
+    fn value_to_yaml() {
+        let binding = Binding::new();
+        let yaml = Yaml::Array(vec![Yaml::Integer(1), Yaml::Hash(Binding::hash_from_str("a: b"))]);
+
+        assert_eq!(yaml, binding.value_to_yaml(binding.yaml_to_value(&yaml)));
+    }
+
+    #[test]
+    fn eval_interpolation() {
+        let mut binding = Binding::new();
+        binding.set_var("a", Yaml::Integer(1));
+        binding.set_var("s", Yaml::from_str("${a}"));
+
+        for e in [
+            ("${a > (0 + 1)}", Value::from(false)),
+            (r#"${"a: b"}"#, Value::from("a: b")),
+            (r#"x${"}"}y"#, Value::from("x}y")),
+            (r#"${"a\"}" + 'b\'c'}"#, Value::from(r#"a"}b'c"#)),
+            (r"${len('a\\b')}", Value::from(3)),
+            ("$${a} ${a}", Value::from("${a} 1")),
+            ("$${a}", Value::from("${a}")),
+            // Values aren't evaluated again.
+            ("${s}!", Value::from("${a}!")),
+        ] {
+            assert_eq!(e.1, binding.eval(&Yaml::from_str(e.0)).unwrap(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn eval_error() {
+        let binding = Binding::new();
+
+        let err = binding.eval(&Yaml::from_str("${x + 1}")).unwrap_err();
+        assert_eq!(ErrorKind::Expression, err.kind);
+
+        let err = binding.eval(&Yaml::from_str("a ${(1 + 2}")).unwrap_err();
+        assert_eq!(ErrorKind::Expression, err.kind);
+        assert_eq!("a ${(1 + 2}: unexpected '}' at column 11", err.to_string());
+    }
+
+    #[test]
+    fn entry_to_list() {
+        let hash = Binding::hash_from_str("{a: [1], b: 1}");
+
+        assert_eq!(1, Binding::entry_to_list(&hash, "a").unwrap().len());
+        assert_eq!("expected 'c'", Binding::entry_to_list(&hash, "c").unwrap_err().to_string());
+        assert_eq!(
+            "expected list for 'b'",
+            Binding::entry_to_list(&hash, "b").unwrap_err().to_string()
+        );
+    }
+
     #[test]
     fn is_truthy() {
         let binding = Binding::new();
 
-        for e in vec![
+        for e in [
             (Yaml::from_str("true"), true),
             (Yaml::from_str("false"), false),
             (Yaml::from_str("1"), true),
             (Yaml::from_str("0"), false),
             (Yaml::from_str("foo"), true),
             (Yaml::String("".into()), false),
+            (Yaml::Array(vec![Yaml::Integer(1)]), true),
+            (Yaml::Array(Array::new()), false),
         ] {
-            assert_eq!(e.1, binding.is_truthy(&e.0), "{e:?}");
+            assert_eq!(e.1, binding.is_truthy(&e.0).unwrap(), "{e:?}");
         }
     }
 }
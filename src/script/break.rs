@@ -1,46 +1,67 @@
-use super::Script;
-use std::io::{Error, ErrorKind::Interrupted};
+use super::{Binding, Flow, Script, ScriptError};
 use yaml_rust2::{yaml::Hash, Yaml};
 
 // - break: [<condition>]
+//   [label: <name>]
 //   [message: <string>]
-pub fn run(s: &mut Script, cond: &Yaml, step: &Hash) -> Result<(), Error> {
-    match s.binding.is_truthy(cond) {
-        true => Err(Error::new(Interrupted, message(step))),
-        false => Ok(()),
+pub fn run(s: &mut Script, cond: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
+    if !s.is_truthy(cond)? {
+        return Ok(Flow::Next);
     }
+
+    Ok(Flow::Break {
+        label: Binding::entry_to_name(step, "label")?.map(String::from),
+        message: message(s, step)?,
+    })
 }
 
-fn message(step: &Hash) -> String {
-    step.get(&Yaml::from_str("message"))
-        .unwrap_or(&Yaml::from_str("(break)"))
-        .as_str()
-        .unwrap()
-        .to_string()
+fn message(s: &mut Script, step: &Hash) -> Result<String, ScriptError> {
+    match step.get(&Yaml::from_str("message")) {
+        Some(yaml) => s.eval_to_string(yaml),
+        None => Ok("(break)".into()),
+    }
 }
 
 //=============================================================================
 
 #[cfg(test)]
 mod tests {
-    use super::super::binding::Binding;
     use super::*;
 
     #[test]
     fn run() {
         let mut script = Script::new(String::new(), None);
 
-        let err = super::run(&mut script, &Yaml::from_str("true"), &Hash::new()).unwrap_err();
-        assert_eq!(Interrupted, err.kind());
-        assert_eq!("(break)", err.to_string());
+        let flow = super::run(&mut script, &Yaml::from_str("true"), &Hash::new()).unwrap();
+        assert_eq!(
+            Flow::Break {
+                label: None,
+                message: "(break)".into()
+            },
+            flow
+        );
+    }
+
+    #[test]
+    fn run_false() {
+        let mut script = Script::new(String::new(), None);
+
+        let flow = super::run(&mut script, &Yaml::from_str("false"), &Hash::new()).unwrap();
+        assert_eq!(Flow::Next, flow);
     }
 
     #[test]
     fn run_message() {
         let mut script = Script::new(String::new(), None);
-        let hash = Binding::hash_from_str("message: foo");
+        let hash = Binding::hash_from_str("{label: outer, message: foo}");
 
-        let err = super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap_err();
-        assert_eq!("foo", err.to_string());
+        let flow = super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap();
+        assert_eq!(
+            Flow::Break {
+                label: Some("outer".into()),
+                message: "foo".into()
+            },
+            flow
+        );
     }
 }
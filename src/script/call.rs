@@ -1,39 +1,75 @@
-use super::Script;
-use std::io::Error;
-use yaml_rust2::{
-    yaml::{Array, Hash},
-    Yaml,
-};
+use super::{params, Binding, Flow, Frame, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
 
 // - call: foo
 //   [with:
 //      <name>: <expression>
 //      ...]
-pub fn run(s: &mut Script, name: &Yaml, step: &Hash) -> Result<(), Error> {
-    let old_params = s.binding.set_params(with(step, "with"));
-    let res = s.run_steps(&steps(s, name));
-    s.binding.params = old_params;
+//   [as: <name>]
+pub fn run(s: &mut Script, name: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
+    let frame = s.eval_to_frame(&with(step, "with")?, "with")?;
+    let val = match run_proc(s, Binding::yaml_to_name(name, "call")?, frame)? {
+        Flow::Return(val) => val,
+        flow => return Ok(flow),
+    };
 
-    res
+    if let Some(name) = Binding::entry_to_name(step, "as")? {
+        s.binding.set_var(name, val);
+    }
+
+    Ok(Flow::Next)
+}
+
+// Runs a proc with the given arguments. Returns the returned value (null if none), or an exit.
+pub fn run_proc(s: &mut Script, name: &str, args: Frame) -> Result<Flow, ScriptError> {
+    let proc = s.binding.proc(name)?;
+    let steps = Binding::entry_to_list(&proc.def, "do")?;
+    let frame = match params::parse(&proc.def)? {
+        Some(params) => params::bind(s, name, &params, args)?,
+        None => args,
+    };
+    let name = s.binding.proc_name(name).unwrap_or_default();
+    let namespace = name.rsplit_once('.').map_or("", |(ns, _)| ns);
+
+    // Paths in a proc are relative to the file that defined it, e.g. one it was imported from.
+    s.binding.push_frame(frame);
+    s.binding.namespaces.push(namespace.into());
+    s.files.push(proc.file.clone());
+    s.marks.push(proc.marks.clone());
+    let res = s.run_steps(steps);
+    s.marks.pop();
+    s.files.pop();
+    s.binding.namespaces.pop();
+    s.binding.pop_frame();
+
+    // Loops don't reach across procs, so a break or continue can't get out of one.
+    match res? {
+        Flow::Next => Ok(Flow::Return(Yaml::Null)),
+        flow @ (Flow::Return(_) | Flow::Exit(_)) => Ok(flow),
+        flow => Err(flow.error()),
+    }
 }
 
-fn with(step: &Hash, key: &str) -> Hash {
-    step.get(&Yaml::from_str(key))
-        .unwrap_or(&Yaml::Hash(Hash::new()))
-        .clone()
-        .into_hash()
-        .expect("expected mapping")
+// Names of the params of a proc, for binding arguments by position.
+pub fn param_names(s: &Script, name: &str) -> Result<Vec<String>, ScriptError> {
+    let params = params::parse(&s.binding.proc(name)?.def)?.unwrap_or_default();
+    Ok(params.into_iter().map(|p| p.name).collect())
 }
 
-fn steps(s: &Script, name: &Yaml) -> Array {
-    s.binding.proc(name.as_str().expect("expected string")).into_vec().unwrap()
+fn with(step: &Hash, key: &str) -> Result<Hash, ScriptError> {
+    match step.get(&Yaml::from_str(key)) {
+        Some(yaml) => {
+            yaml.clone().into_hash().ok_or_else(|| ScriptError::type_mismatch("mapping", key))
+        }
+        None => Ok(Hash::new()),
+    }
 }
 
 //=============================================================================
 
 #[cfg(test)]
 mod tests {
-    use super::super::Binding;
+    use super::super::ErrorKind;
     use super::*;
     use yaml_rust2::{yaml::Hash, Yaml};
 
@@ -84,4 +120,206 @@ mod tests {
         assert_eq!("bar: a=2, b=1", script.writer.log[0]);
         assert_eq!("foo: a=1", script.writer.log[1]);
     }
+
+    #[test]
+    fn run_local() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script.run_str("[{def: foo, do: [local: {a: 1}, a: '${a + 1}', echo: '${a}']}]").unwrap();
+
+        super::run(&mut script, &Yaml::from_str("foo"), &Hash::new()).unwrap();
+        assert_eq!("2", script.writer.log[0]);
+        assert_eq!(None, script.binding.vars.get("a"));
+        assert!(script.binding.frames.is_empty());
+    }
+
+    #[test]
+    fn run_lexical() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+
+        #[rustfmt::skip]
+        let lines = [
+            "- def: bar",
+            "  do:",
+            "    - echo: ${a}",
+            "",
+            "- def: foo",
+            "  do:",
+            "    - local: {a: 1}",
+            "    - call: bar",
+        ];
+
+        script.run_str(&lines.join("\n")).unwrap();
+
+        super::run(&mut script, &Yaml::from_str("foo"), &Hash::new()).unwrap();
+        assert_eq!("null", script.writer.log[0]);
+    }
+
+    #[test]
+    fn run_recursive() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("with: {n: 2}");
+
+        #[rustfmt::skip]
+        let lines = [
+            "- def: count",
+            "  do:",
+            "    - local: {m: '${n * 10}'}",
+            "    - if: ${n > 0}",
+            "      then:",
+            "        - call: count",
+            "          with:",
+            "            n: ${n - 1}",
+            "    - echo: '${n}, ${m}'",
+        ];
+
+        script.run_str(&lines.join("\n")).unwrap();
+
+        super::run(&mut script, &Yaml::from_str("count"), &hash).unwrap();
+        assert_eq!(vec!["0, 0", "1, 10", "2, 20"], script.writer.log);
+        assert!(script.binding.vars.is_empty());
+    }
+
+    #[test]
+    fn run_return() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{with: {x: 2}, as: y}");
+
+        #[rustfmt::skip]
+        let lines = [
+            "- def: double",
+            "  do:",
+            "    - while: true",
+            "      do:",
+            "        - return: ${x * 2}",
+            "    - echo: unreachable",
+        ];
+
+        script.run_str(&lines.join("\n")).unwrap();
+
+        super::run(&mut script, &Yaml::from_str("double"), &hash).unwrap();
+        assert_eq!(4, script.binding.var("y").as_i64().unwrap());
+        assert!(script.writer.log.is_empty());
+    }
+
+    #[test]
+    fn run_return_nested() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("as: y");
+
+        #[rustfmt::skip]
+        let lines = [
+            "- def: bar",
+            "  do:",
+            "    - return: 1",
+            "",
+            "- def: foo",
+            "  do:",
+            "    - call: bar",
+            "      as: a",
+            "    - return: ${a + 1}",
+        ];
+
+        script.run_str(&lines.join("\n")).unwrap();
+
+        super::run(&mut script, &Yaml::from_str("foo"), &hash).unwrap();
+        assert_eq!(2, script.binding.var("y").as_i64().unwrap());
+    }
+
+    #[test]
+    fn run_no_return() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("as: y");
+        script.run_str("[{def: foo, do: []}, y: 1]").unwrap();
+
+        super::run(&mut script, &Yaml::from_str("foo"), &hash).unwrap();
+        assert_eq!(Yaml::Null, script.binding.var("y"));
+    }
+
+    #[test]
+    fn run_break() {
+        let mut script = Script::new(String::new(), None);
+        script.run_str("[{def: foo, do: [break: true]}]").unwrap();
+
+        let err = script.run_str("[{while: true, do: [call: foo]}]").unwrap_err();
+        assert_eq!(ErrorKind::Flow, err.kind);
+        assert_eq!("1:21: 'break' outside of a loop", err.to_string());
+    }
+
+    #[test]
+    fn run_exit() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("as: y");
+        script.run_str("[{def: foo, do: [local: {a: 1}, exit: 2]}]").unwrap();
+
+        let flow = super::run(&mut script, &Yaml::from_str("foo"), &hash).unwrap();
+        assert_eq!(Flow::Exit(2), flow);
+        assert_eq!(Yaml::Null, script.binding.var("y"));
+        assert!(script.binding.frames.is_empty());
+    }
+
+    #[test]
+    fn run_params() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("with: {a: 1}");
+        script
+            .run_str("[{def: foo, params: [a, {name: b, default: 2}], do: [echo: '${a + b}']}]")
+            .unwrap();
+
+        super::run(&mut script, &Yaml::from_str("foo"), &hash).unwrap();
+        assert_eq!("3", script.writer.log[0]);
+    }
+
+    #[test]
+    fn run_params_string() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("with: {s: '${t}'}");
+        script
+            .run_str(
+                "[t: '007', {def: foo, params: [{name: s, type: string}], do: [echo: '${s}']}]",
+            )
+            .unwrap();
+
+        super::run(&mut script, &Yaml::from_str("foo"), &hash).unwrap();
+        assert_eq!("007", script.writer.log[0]);
+    }
+
+    #[test]
+    fn run_params_error() {
+        let mut script = Script::new("foo.ys".into(), None);
+
+        #[rustfmt::skip]
+        let lines = [
+            "- def: foo",
+            "  params:",
+            "    - name: n",
+            "      type: integer",
+            "  do: []",
+            "",
+            "- call: foo",
+            "  with:",
+            "    n: x",
+        ];
+
+        let err = script.run_str(&lines.join("\n")).unwrap_err();
+        assert_eq!(ErrorKind::TypeMismatch, err.kind);
+        assert_eq!("foo.ys:7:3: foo: expected integer for 'n', found string", err.to_string());
+    }
+
+    #[test]
+    fn run_error() {
+        let mut script = Script::new(String::new(), None);
+        script.run_str("[{def: foo, do: [local: {a: 1}, exit: foo]}]").unwrap();
+
+        let err = super::run(&mut script, &Yaml::from_str("foo"), &Hash::new()).unwrap_err();
+        assert_eq!(ErrorKind::TypeMismatch, err.kind);
+        assert!(script.binding.frames.is_empty());
+    }
+
+    #[test]
+    fn run_unknown() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Yaml::from_str("foo"), &Hash::new()).unwrap_err();
+        assert_eq!(ErrorKind::UnknownStep, err.kind);
+    }
 }
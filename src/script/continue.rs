@@ -0,0 +1,38 @@
+use super::{Binding, Flow, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - continue: [<condition>]
+//   [label: <name>]
+pub fn run(s: &mut Script, cond: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
+    if !s.is_truthy(cond)? {
+        return Ok(Flow::Next);
+    }
+
+    Ok(Flow::Continue {
+        label: Binding::entry_to_name(step, "label")?.map(String::from),
+    })
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("label: outer");
+
+        let flow = super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap();
+        assert_eq!(
+            Flow::Continue {
+                label: Some("outer".into())
+            },
+            flow
+        );
+
+        let flow = super::run(&mut script, &Yaml::from_str("false"), &hash).unwrap();
+        assert_eq!(Flow::Next, flow);
+    }
+}
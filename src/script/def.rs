@@ -1,17 +1,33 @@
-use super::{Binding, Script};
-use std::io::Error;
+use super::binding::Proc;
+use super::{loader, params, Binding, Script, ScriptError};
+use std::sync::Arc;
 use yaml_rust2::{yaml::Hash, Yaml};
 
 // - def: <name>
+//   [params: <params>]
 //   do: <steps>
-pub fn run(s: &mut Script, name: &Yaml, step: &Hash) -> Result<(), Error> {
+pub fn run(s: &mut Script, name: &Yaml, step: &Hash) -> Result<(), ScriptError> {
     // ???: Need validation. Name must be an identifier.
-    let steps = Yaml::Array(Binding::entry_to_list(step, "do"));
-    s.binding.set_proc(name.as_str().expect("expected string"), steps);
+    Binding::entry_to_list(step, "do")?;
+    params::parse(step)?;
+
+    let proc = proc(s, step);
+    s.binding.set_proc(Binding::yaml_to_name(name, "def")?, proc);
 
     Ok(())
 }
 
+// A proc of a copy of a step, which is kept after the document that it's in, in the current file.
+pub fn proc(s: &Script, step: &Hash) -> Proc {
+    let def = step.clone();
+
+    Proc {
+        marks: Arc::new(loader::copy(&s.marks, step, &def)),
+        file: s.file().into(),
+        def,
+    }
+}
+
 //=============================================================================
 
 #[cfg(test)]
@@ -22,9 +38,17 @@ mod tests {
     fn run() {
         let mut script = Script::new(String::new(), None);
         let hash = Binding::hash_from_str("do: [a: 42]");
-        let steps = Yaml::Array(Binding::entry_to_list(&hash, "do"));
 
         super::run(&mut script, &Yaml::from_str("foo"), &hash).unwrap();
-        assert_eq!(steps, script.binding.proc("foo"));
+        assert_eq!(hash, script.binding.proc("foo").unwrap().def);
+    }
+
+    #[test]
+    fn run_params() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{params: [1], do: []}");
+
+        let err = super::run(&mut script, &Yaml::from_str("foo"), &hash).unwrap_err();
+        assert_eq!("expected name or mapping for 'params'", err.to_string());
     }
 }
@@ -0,0 +1,253 @@
+use super::{call, task, Binding, ErrorKind, Flow, Frame, Script, ScriptError};
+use std::{collections::HashSet, slice};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// A document of a script: a list of steps, or a mapping with a name, so that it can be run as a
+// task, e.g. `ys make.ys --task build`.
+//
+// name: <name>
+// [description: <string>]
+// [requires: <list of names>]
+// do: <steps>
+#[derive(Debug, Default, PartialEq)]
+pub struct Doc<'a> {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub requires: Vec<String>,
+    pub steps: &'a [Yaml],
+}
+
+// A mapping without steps is the header of a script, not a document, if it has any of the keys of
+// a header, or of a named document (which is an error, see `header`). Others are documents, which
+// are missing their names.
+pub fn is_header(yaml: &Yaml) -> bool {
+    let has = |hash: &Hash, key: &str| hash.contains_key(&Yaml::from_str(key));
+
+    yaml.as_hash().is_some_and(|hash| {
+        !has(hash, "do")
+            && ["help", "params", "name", "description", "requires"].iter().any(|k| has(hash, k))
+    })
+}
+
+pub fn parse(yaml: &Yaml) -> Result<Doc<'_>, ScriptError> {
+    let hash = match yaml {
+        Yaml::Array(steps) => {
+            return Ok(Doc {
+                steps,
+                ..Doc::default()
+            })
+        }
+        Yaml::Hash(hash) => hash,
+        Yaml::BadValue | Yaml::Null => return Ok(Doc::default()),
+        _ => return Err(ScriptError::type_mismatch("list or mapping", "script")),
+    };
+
+    let name =
+        Binding::entry_to_name(hash, "name")?.ok_or_else(|| ScriptError::missing_key("name"))?;
+    let requires = Binding::entry_to_list_or_empty(hash, "requires")?
+        .iter()
+        .map(|r| Binding::yaml_to_name(r, "requires").map(String::from))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Doc {
+        name: Some(name.into()),
+        description: string(hash, "description")?,
+        requires,
+        steps: Binding::entry_to_list(hash, "do")?,
+    })
+}
+
+fn string(hash: &Hash, key: &str) -> Result<Option<String>, ScriptError> {
+    match hash.get(&Yaml::from_str(key)) {
+        Some(Yaml::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(ScriptError::type_mismatch("string", key)),
+        None => Ok(None),
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// Runs the documents in order, each after those it requires, and only once. With a task, only the
+// unnamed documents are run (e.g. to define procs and variables), then the task, which is the
+// name of a document, a task (see `task`), or a proc.
+pub fn run(s: &mut Script, docs: &[Doc], task: Option<&str>) -> Result<Flow, ScriptError> {
+    let mut runner = Runner {
+        docs,
+        done: HashSet::new(),
+        running: Vec::new(),
+    };
+
+    for (i, doc) in docs.iter().enumerate() {
+        if task.is_some() && doc.name.is_some() {
+            continue;
+        }
+
+        match runner.run_doc(s, i)? {
+            Flow::Next => (),
+            flow => return Ok(flow),
+        }
+    }
+
+    match task {
+        Some(task) => runner.run_task(s, task),
+        None => Ok(Flow::Next),
+    }
+}
+
+struct Runner<'a> {
+    docs: &'a [Doc<'a>],
+    // Names of the documents that have been run, and of those being run, innermost last.
+    done: HashSet<String>,
+    running: Vec<String>,
+}
+
+impl Runner<'_> {
+    fn run_task(&mut self, s: &mut Script, name: &str) -> Result<Flow, ScriptError> {
+        if let Some(i) = self.docs.iter().position(|d| d.name.as_deref() == Some(name)) {
+            return self.run_doc(s, i);
+        }
+
+        if task::is_task(s, name) {
+            return task::run_tasks(s, &[name.into()]);
+        }
+
+        if s.binding.proc_name(name).is_none() {
+            return Err(ScriptError::new(ErrorKind::Argument, format!("unknown task '{name}'")));
+        }
+
+        match call::run_proc(s, name, Frame::new())? {
+            Flow::Return(_) => Ok(Flow::Next),
+            flow => Ok(flow),
+        }
+    }
+
+    fn run_doc(&mut self, s: &mut Script, i: usize) -> Result<Flow, ScriptError> {
+        let doc = &self.docs[i];
+
+        if let Some(name) = &doc.name {
+            if self.done.contains(name) {
+                return Ok(Flow::Next);
+            }
+
+            if self.running.contains(name) {
+                let cycle = [&self.running[..], slice::from_ref(name)].concat().join(" -> ");
+                return Err(ScriptError::new(ErrorKind::Flow, format!("cycle: {cycle}")));
+            }
+
+            self.running.push(name.clone());
+        }
+
+        for name in &doc.requires {
+            match self.run_task(s, name)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+
+        let flow = s.run_steps(doc.steps)?;
+
+        if let Some(name) = &doc.name {
+            self.running.pop();
+            self.done.insert(name.clone());
+        }
+
+        Ok(flow)
+    }
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust2::YamlLoader;
+
+    fn run(text: &str, task: Option<&str>) -> Result<Vec<String>, ScriptError> {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let docs = YamlLoader::load_from_str(text).unwrap();
+        let docs: Vec<Doc> = docs.iter().map(super::parse).collect::<Result<_, _>>()?;
+
+        super::run(&mut script, &docs, task)?;
+        Ok(script.writer.log)
+    }
+
+    const TEXT: &str = "
+- echo: setup
+- def: hello
+  do: [echo: hello]
+---
+name: build
+requires: [clean]
+do: [echo: build]
+---
+name: clean
+description: Removes everything.
+do: [echo: clean]
+";
+
+    #[test]
+    fn parse() {
+        let yaml = &YamlLoader::load_from_str(TEXT).unwrap()[2];
+
+        assert_eq!(
+            Doc {
+                name: Some("clean".into()),
+                description: Some("Removes everything.".into()),
+                requires: vec![],
+                steps: YamlLoader::load_from_str("[echo: clean]").unwrap()[0].as_vec().unwrap(),
+            },
+            super::parse(yaml).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for e in [
+            ("do: []", "expected 'name'"),
+            ("{name: a, do: 1}", "expected list for 'do'"),
+            ("{name: a, requires: [[]], do: []}", "expected name for 'requires'"),
+            ("{name: a, description: [], do: []}", "expected string for 'description'"),
+            ("1", "expected list or mapping for 'script'"),
+        ] {
+            let yaml = &YamlLoader::load_from_str(e.0).unwrap()[0];
+            assert_eq!(e.1, super::parse(yaml).unwrap_err().to_string(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn is_header() {
+        assert!(super::is_header(&Yaml::Hash(Binding::hash_from_str("params: []"))));
+        assert!(!super::is_header(&Yaml::Hash(Binding::hash_from_str("{name: a, do: []}"))));
+        assert!(super::is_header(&Yaml::Hash(Binding::hash_from_str("{name: a, steps: []}"))));
+        assert!(!super::is_header(&Yaml::Hash(Binding::hash_from_str("echo: hi"))));
+        assert!(!super::is_header(&Yaml::Array(vec![])));
+    }
+
+    #[test]
+    fn run_all() {
+        assert_eq!(vec!["setup", "clean", "build"], run(TEXT, None).unwrap());
+    }
+
+    #[test]
+    fn run_task() {
+        assert_eq!(vec!["setup", "clean", "build"], run(TEXT, Some("build")).unwrap());
+        assert_eq!(vec!["setup", "clean"], run(TEXT, Some("clean")).unwrap());
+        assert_eq!(vec!["setup", "hello"], run(TEXT, Some("hello")).unwrap());
+    }
+
+    #[test]
+    fn run_unknown() {
+        let err = run(TEXT, Some("foo")).unwrap_err();
+        assert_eq!(ErrorKind::Argument, err.kind);
+        assert_eq!("unknown task 'foo'", err.to_string());
+    }
+
+    #[test]
+    fn run_cycle() {
+        let text = "{name: a, requires: [b], do: []}\n---\n{name: b, requires: [a], do: []}";
+
+        let err = run(text, None).unwrap_err();
+        assert_eq!(ErrorKind::Flow, err.kind);
+        assert_eq!("cycle: a -> b -> a", err.to_string());
+    }
+}
@@ -1,32 +1,118 @@
-use super::{var, Binding, Script};
-use std::io::{Error, ErrorKind::Interrupted};
+use super::{exec, flow, parallel, var, Binding, Flow, Script, ScriptError};
+use std::sync::Mutex;
 use yaml_rust2::{
     yaml::{Array, Hash},
     Yaml,
 };
 
 // - each: <var>
-//   in: <list>
+//   in: <list> | <expression => list | map>
+//   [label: <name>]
+//   [parallel: <number>]
+//   [fail_fast: <boolean>]
+//   [as: <name>]
+//   [result: <expression>]
 //   do: <steps>
-pub fn run(s: &mut Script, name: &Yaml, step: &Hash) -> Result<(), Error> {
+pub fn run(s: &mut Script, name: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
     // ???: Need validation. Name must be an identifier.
-    let var_name = name.as_str().expect("expected string");
-    let items = Binding::entry_to_list(step, "in");
-    let steps = Binding::entry_to_list(step, "do");
+    let var_name = Binding::yaml_to_name(name, "each")?;
+    let items = items(s, step)?;
+    let steps = Binding::entry_to_list(step, "do")?;
+    let label = Binding::entry_to_name(step, "label")?;
 
-    match run_steps(s, var_name, &items, &steps) {
-        Err(e) if e.kind() == Interrupted => Ok(()),
-        res => res,
+    if step.contains_key(&Yaml::from_str("parallel")) {
+        return run_parallel(s, var_name, &items, steps, label, step);
+    }
+
+    run_steps(s, var_name, &items, steps, label)
+}
+
+// Maps are iterated by key, in order.
+fn items(s: &mut Script, step: &Hash) -> Result<Array, ScriptError> {
+    let expr = step.get(&Yaml::from_str("in")).ok_or_else(|| ScriptError::missing_key("in"))?;
+
+    match s.eval_to_yaml(expr)? {
+        Yaml::Array(list) => Ok(list),
+        Yaml::Hash(hash) => Ok(hash.into_iter().map(|(k, _)| k).collect()),
+        Yaml::Null => Ok(Array::new()),
+        _ => Err(ScriptError::type_mismatch("list", "in")),
     }
 }
 
-pub fn run_steps(s: &mut Script, name: &str, items: &Array, steps: &Array) -> Result<(), Error> {
+pub fn run_steps(
+    s: &mut Script,
+    name: &str,
+    items: &Array,
+    steps: &[Yaml],
+    label: Option<&str>,
+) -> Result<Flow, ScriptError> {
     for item in items {
-        var::run(s, name, &item)?;
-        s.run_steps(steps)?;
+        var::run(s, name, item)?;
+
+        if let Some(flow) = flow::run_loop_steps(s, steps, label)? {
+            return Ok(flow);
+        }
     }
 
-    Ok(())
+    Ok(Flow::Next)
+}
+
+// Runs the steps for up to `parallel` items at once, each on a thread with its own copy of the
+// variables (see `parallel`). A break stops any more items from being started, and with
+// `fail_fast: true`, so does an error, but items that are already running still finish. The result
+// of each item is its `result` (default `_`, e.g. the result of its last exec), evaluated after its
+// steps, and they're bound to `as` as a list, in order, with null for those that didn't finish.
+fn run_parallel(
+    s: &mut Script,
+    name: &str,
+    items: &Array,
+    steps: &[Yaml],
+    label: Option<&str>,
+    step: &Hash,
+) -> Result<Flow, ScriptError> {
+    let max = parallel::max(s, step, "parallel")?;
+    let fail_fast = exec::flag(step, "fail_fast")?;
+    let as_name = Binding::entry_to_name(step, "as")?;
+    let result = step.get(&Yaml::from_str("result"));
+    let list = Mutex::new(vec![Yaml::Null; items.len()]);
+
+    let run_item = |s: &mut Script, i: usize| {
+        var::run(s, name, &items[i])?;
+
+        match s.run_steps(steps)? {
+            Flow::Next => (),
+            flow @ Flow::Continue { .. } if flow.is_for(label) => (),
+            flow => return Ok(flow),
+        }
+
+        let val = match result {
+            Some(expr) => s.eval_to_yaml(expr)?,
+            None => s.binding.var("_"),
+        };
+        list.lock().unwrap()[i] = val;
+        Ok(Flow::Next)
+    };
+    let stop = |res: &Result<Flow, ScriptError>| match res {
+        Ok(flow) => flow.is_for(label),
+        Err(_) => fail_fast,
+    };
+    let mut results = parallel::run_branches(s, items.len(), max, run_item, stop);
+
+    // Breaks end the loop, but other flows can't leave the thread they're on, and are errors (see
+    // `parallel::join`).
+    for (_, res) in results.iter_mut() {
+        if matches!(res, Ok(flow) if flow.is_for(label)) {
+            *res = Ok(Flow::Next);
+        }
+    }
+
+    let flow = parallel::join(s, results, &[])?;
+
+    if let Some(as_name) = as_name {
+        s.binding.set_var(as_name, Yaml::Array(list.into_inner().unwrap()));
+    }
+
+    Ok(flow)
 }
 
 //=============================================================================
@@ -34,6 +120,7 @@ pub fn run_steps(s: &mut Script, name: &str, items: &Array, steps: &Array) -> Re
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Instant;
 
     #[test]
     fn run() {
@@ -45,4 +132,198 @@ mod tests {
         assert_eq!("1", script.writer.log[0]);
         assert_eq!("2", script.writer.log[1]);
     }
+
+    #[test]
+    fn run_expr() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{in: '${items}', do: [echo: '${x}']}");
+        script.binding.set_var("items", Yaml::Array(vec![Yaml::Integer(1), Yaml::Integer(2)]));
+
+        super::run(&mut script, &Yaml::from_str("x"), &hash).unwrap();
+        assert_eq!(vec!["1", "2"], script.writer.log);
+    }
+
+    #[test]
+    fn run_range() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{in: '${range(1, 3)}', do: [echo: '${x}']}");
+
+        super::run(&mut script, &Yaml::from_str("x"), &hash).unwrap();
+        assert_eq!(vec!["1", "2"], script.writer.log);
+    }
+
+    #[test]
+    fn run_map() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{in: '${cfg}', do: [echo: '${x}']}");
+        script.binding.set_var("cfg", Yaml::Hash(Binding::hash_from_str("{zeta: 1, alpha: 2}")));
+
+        super::run(&mut script, &Yaml::from_str("x"), &hash).unwrap();
+        assert_eq!(vec!["zeta", "alpha"], script.writer.log);
+    }
+
+    #[test]
+    fn run_continue() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash =
+            Binding::hash_from_str("{in: [1, 2, 3], do: [continue: '${x == 2}', echo: '${x}']}");
+
+        super::run(&mut script, &Yaml::from_str("x"), &hash).unwrap();
+        assert_eq!(vec!["1", "3"], script.writer.log);
+    }
+
+    #[test]
+    fn run_label() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+
+        #[rustfmt::skip]
+        let lines = [
+            "- each: x",
+            "  in: [1, 2, 3]",
+            "  label: outer",
+            "  do:",
+            "    - each: y",
+            "      in: [1, 2, 3]",
+            "      do:",
+            "        - continue: ${y == 2}",
+            "          label: outer",
+            "        - break: ${x == 3}",
+            "          label: outer",
+            "        - echo: ${x}${y}",
+        ];
+
+        script.run_str(&lines.join("\n")).unwrap();
+        assert_eq!(vec!["11", "21"], script.writer.log);
+    }
+
+    #[test]
+    fn run_parallel() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+
+        #[rustfmt::skip]
+        let lines = [
+            "- each: x",
+            "  in: [3, 1, 2]",
+            "  parallel: 3",
+            "  as: results",
+            "  result: ${x * 10}",
+            "  do:",
+            "    - exec: sleep 0.${x}",
+            "- each: x",
+            "  in: [1, 2]",
+            "  parallel: 2",
+            "  as: outputs",
+            "  do:",
+            "    - exec: echo ${x}",
+        ];
+
+        let start = Instant::now();
+        script.run_str(&lines.join("\n")).unwrap();
+        assert!(start.elapsed().as_secs_f64() < 0.55);
+
+        let results = script.binding.var("results");
+        let results: Vec<i64> = results.as_vec().unwrap().iter().flat_map(Yaml::as_i64).collect();
+        assert_eq!(vec![30, 10, 20], results);
+
+        let outputs = script.binding.var("outputs");
+        assert_eq!("2", outputs[1]["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_parallel_fail_fast() {
+        let run = |fail_fast: bool| {
+            let mut script = Script::new(String::new(), Some(Vec::new()));
+            let text = format!(
+                "[{{each: x, in: [1, 2, 3], parallel: 1, fail_fast: {fail_fast}, do: [{}]}}]",
+                "{exec: 'sh -c \"exit $0\" ${x - 1}', check: true}, {echo: '${x}'}"
+            );
+
+            let err = script.run_str(&text).unwrap_err();
+            (script.writer.log, err.message)
+        };
+
+        let (log, message) = run(true);
+        assert_eq!(vec!["1"], log);
+        assert!(message.ends_with("exit status: 1"), "{message}");
+
+        let (log, message) = run(false);
+        assert_eq!(vec!["1"], log);
+        assert!(message.starts_with("2 of 3 branches failed: "), "{message}");
+    }
+
+    #[test]
+    fn run_parallel_break() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let text = concat!(
+            "[{each: x, in: [1, 2, 3, 4], parallel: 1, as: res,",
+            " do: [{continue: '${x == 1}'}, {break: '${x == 3}'}, {_: '${x}'}]}]"
+        );
+
+        script.run_str(text).unwrap();
+        let res = script.binding.var("res");
+        assert_eq!(Yaml::Null, res[0]);
+        assert_eq!(2, res[1].as_i64().unwrap());
+        assert_eq!(Yaml::Null, res[2]);
+        assert_eq!(Yaml::Null, res[3]);
+    }
+
+    #[test]
+    fn run_parallel_flows() {
+        let item = "{each: x, in: [1], parallel: 1, do: [%]}";
+
+        for e in [
+            ("[{def: f, do: [%]}, call: f]", "return: 1", "'return'"),
+            (
+                "[{each: y, in: [1], label: a, do: [%]}]",
+                "{break: true, label: a}",
+                "'break' to 'a'",
+            ),
+            (
+                "[{while: true, label: b, do: [%]}]",
+                "{continue: true, label: b}",
+                "'continue' to 'b'",
+            ),
+        ] {
+            let mut script = Script::new(String::new(), None);
+            let text = e.0.replace('%', &item.replace('%', e.1));
+
+            let err = script.run_str(&text).unwrap_err();
+            assert_eq!(super::super::ErrorKind::Flow, err.kind);
+            assert_eq!(format!("{} can't leave a parallel branch", e.2), err.message, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn run_missing() {
+        let mut script = Script::new(String::new(), None);
+
+        for e in [
+            ("{im: [1], do: []}", "expected 'in'"),
+            ("{in: [1]}", "expected 'do'"),
+        ] {
+            let hash = Binding::hash_from_str(e.0);
+            let err = super::run(&mut script, &Yaml::from_str("x"), &hash).unwrap_err();
+            assert_eq!(super::super::ErrorKind::MissingKey, err.kind);
+            assert_eq!(e.1, err.to_string(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn run_not_list() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{in: 1, do: []}");
+
+        let err = super::run(&mut script, &Yaml::from_str("x"), &hash).unwrap_err();
+        assert_eq!("expected list for 'in'", err.to_string());
+    }
+
+    #[test]
+    fn run_name() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{in: [1, 2], do: []}");
+
+        let err = super::run(&mut script, &Yaml::Integer(1), &hash).unwrap_err();
+        assert_eq!(super::super::ErrorKind::TypeMismatch, err.kind);
+        assert_eq!("expected name for 'each'", err.to_string());
+    }
 }
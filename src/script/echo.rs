@@ -1,13 +1,13 @@
-use super::Script;
-use std::io::Error;
+use super::{Script, ScriptError};
 use yaml_rust2::Yaml;
 
 // - echo: <expression>
-pub fn run(s: &mut Script, expr: &Yaml) -> Result<(), Error> {
-    write(s, s.binding.eval_to_string(expr))
+pub fn run(s: &mut Script, expr: &Yaml) -> Result<(), ScriptError> {
+    let val = s.eval_to_string(expr)?;
+    write(s, val)
 }
 
-pub fn write(s: &mut Script, val: String) -> Result<(), Error> {
+pub fn write(s: &mut Script, val: String) -> Result<(), ScriptError> {
     s.writer.write(val);
     Ok(())
 }
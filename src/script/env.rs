@@ -0,0 +1,70 @@
+use super::{exec, Script, ScriptError};
+use std::env;
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - env:
+//     <name>: <expression>
+//     ...
+//
+// Sets environment variables for the commands that the script runs from now on, or removes those
+// set to null. The environment is the `env` variable, which starts as that of the script.
+pub fn run(s: &mut Script, vars: &Yaml) -> Result<(), ScriptError> {
+    let hash = vars.as_hash().ok_or_else(|| ScriptError::type_mismatch("mapping", "env"))?;
+    let frame = s.eval_to_frame(hash, "env")?;
+    let mut env = match s.binding.vars.remove("env") {
+        Some(Yaml::Hash(env)) => env,
+        _ => process(),
+    };
+
+    for (name, val) in frame {
+        match val {
+            Yaml::Null => env.remove(&Yaml::String(name)),
+            _ => env.insert(Yaml::String(name), Yaml::String(exec::yaml_to_string(s, &val))),
+        };
+    }
+
+    s.binding.vars.insert("env".into(), Yaml::Hash(env));
+    Ok(())
+}
+
+// The environment of the script's process.
+pub fn process() -> Hash {
+    env::vars_os()
+        .map(|(name, val)| {
+            let name = name.to_string_lossy().to_string();
+            (Yaml::String(name), Yaml::String(val.to_string_lossy().to_string()))
+        })
+        .collect()
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::Binding;
+    use super::*;
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("env: {A: '${1 + 1}', HOME: null}");
+
+        super::run(&mut script, &hash[&Yaml::from_str("env")]).unwrap();
+        let env = script.binding.var("env");
+        assert_eq!("2", env["A"].as_str().unwrap());
+        assert!(env["HOME"].is_badvalue());
+        assert!(!env["PATH"].is_badvalue());
+
+        let hash = Binding::hash_from_str("shell: true");
+        exec::run(&mut script, &Yaml::from_str("echo $A.$HOME."), &hash).unwrap();
+        assert_eq!("2..", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_not_mapping() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Yaml::from_str("a")).unwrap_err();
+        assert_eq!("expected mapping for 'env'", err.to_string());
+    }
+}
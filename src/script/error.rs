@@ -0,0 +1,166 @@
+use std::{fmt, io};
+use yaml_rust2::{scanner::Marker, ScanError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+    Parse,
+    UnknownStep,
+    MissingKey,
+    TypeMismatch,
+    Expression,
+    Argument,
+    Exec,
+    Io,
+    Flow,
+}
+
+// Names for scripts, e.g. `${error.kind == 'exec'}` in a `catch`.
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Parse => "parse",
+            ErrorKind::UnknownStep => "unknown_step",
+            ErrorKind::MissingKey => "missing_key",
+            ErrorKind::TypeMismatch => "type_mismatch",
+            ErrorKind::Expression => "expression",
+            ErrorKind::Argument => "argument",
+            ErrorKind::Exec => "exec",
+            ErrorKind::Io => "io",
+            ErrorKind::Flow => "flow",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub path: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Location {
+    pub fn new<S: Into<String>>(path: S, mark: &Marker) -> Self {
+        Self {
+            path: path.into(),
+            line: mark.line(),
+            col: mark.col() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.is_empty() {
+            true => write!(f, "{}:{}", self.line, self.col),
+            false => write!(f, "{}:{}:{}", self.path, self.line, self.col),
+        }
+    }
+}
+
+//=============================================================================
+
+#[derive(Debug)]
+pub struct ScriptError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub location: Option<Location>,
+    // The exit code of a failed command, if any.
+    pub code: Option<i32>,
+}
+
+impl ScriptError {
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            location: None,
+            code: None,
+        }
+    }
+
+    pub fn parse<S: Into<String>>(path: S, err: ScanError) -> Self {
+        Self::new(ErrorKind::Parse, err.info()).at(Some(&Location::new(path, err.marker())))
+    }
+
+    pub fn missing_key(key: &str) -> Self {
+        Self::new(ErrorKind::MissingKey, format!("expected '{key}'"))
+    }
+
+    pub fn type_mismatch<S: Into<String>>(expected: &str, what: S) -> Self {
+        Self::new(ErrorKind::TypeMismatch, format!("expected {expected} for '{}'", what.into()))
+    }
+
+    // Sets the location, unless a more specific one was already set by a nested step.
+    pub fn at(mut self, location: Option<&Location>) -> Self {
+        if self.location.is_none() {
+            self.location = location.cloned();
+        }
+
+        self
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(loc) => write!(f, "{loc}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<io::Error> for ScriptError {
+    fn from(err: io::Error) -> Self {
+        Self::new(ErrorKind::Io, err.to_string())
+    }
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust2::YamlLoader;
+
+    #[test]
+    fn display() {
+        let loc = Location {
+            path: "foo.ys".into(),
+            line: 12,
+            col: 5,
+        };
+
+        let err = ScriptError::type_mismatch("list", "do").at(Some(&loc));
+        assert_eq!("foo.ys:12:5: expected list for 'do'", err.to_string());
+        assert_eq!("type_mismatch", err.kind.to_string());
+    }
+
+    #[test]
+    fn parse() {
+        let err = YamlLoader::load_from_str("- [a").unwrap_err();
+
+        let err = ScriptError::parse("foo.ys", err);
+        assert_eq!(ErrorKind::Parse, err.kind);
+        assert_eq!("foo.ys", err.location.unwrap().path);
+    }
+
+    #[test]
+    fn at_keeps_location() {
+        let inner = Location {
+            path: String::new(),
+            line: 2,
+            col: 3,
+        };
+        let outer = Location {
+            line: 1,
+            ..inner.clone()
+        };
+
+        let err = ScriptError::missing_key("do").at(Some(&inner)).at(Some(&outer));
+        assert_eq!(Some(inner), err.location);
+    }
+}
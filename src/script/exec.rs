@@ -1,41 +1,304 @@
-use super::{var, Script};
-use std::io::Error;
-use std::process::{Command, Output};
-use ternop::ternary;
+use super::redirect::{self, Redirect};
+use super::{process, words, Binding, ErrorKind, Frame, Script, ScriptError, Writer};
+use std::{
+    io,
+    process::{Command, Output, Stdio},
+    sync::mpsc,
+    time::Duration,
+};
 use yaml_rust2::{yaml::Hash, Yaml};
 
 // - exec: <expression>
+//   [args: <list> | <expression => list>]
+//   [shell: <boolean>]
+//   [pipe: <list of expressions>]
+//   [env: <map>]
+//   [clear_env: <boolean>]
+//   [cwd: <path>]
+//   [timeout: <seconds>]
+//   [stream: <boolean>]
+//   [stdin: <expression> | {file: <path>} | {var: <name>}]
+//   [stdout: <path> | {file: <path>, [append: <boolean>]} | {var: <name>}]
+//   [stderr: <path> | {file: <path>, [append: <boolean>]} | {var: <name>}]
 //   [as: <name>]
-pub fn run(s: &mut Script, expr: &Yaml, step: &Hash) -> Result<(), Error> {
-    let text = text(command(s.binding.eval_to_string(expr)).output()?);
-    var::run(s, var(step).unwrap_or("_".into()), &Yaml::String(text))
+//   [check: <boolean>]
+//
+// The line is split into words like a shell would, unless `args` are given, in which case it's
+// just the program. With `shell: true` it's run by `sh -c` instead, with `args` as `$1`, `$2`, etc.
+//
+// The stdout of the command is piped to the stdin of each `pipe` command in turn. `stdout`,
+// `stderr`, and the result are those of the last command, as in a shell.
+//
+// With `stream: true`, output that isn't redirected is written as it comes, instead of being kept
+// for the result, unless there's an `as`.
+//
+// The result is bound to `as` (default `_`) as a map of code, stdout, stderr, and ok. With
+// `check: true`, a non-zero exit code is then an error, with the end of stderr in its message.
+pub fn run(s: &mut Script, expr: &Yaml, step: &Hash) -> Result<(), ScriptError> {
+    let line = s.eval_to_string(expr)?;
+    let (commands, line) = commands(s, line, step)?;
+    let [stdin, stdout, stderr] = redirects(s, step)?;
+    let stream = flag(step, "stream")?;
+    let opts = Options {
+        input: input(s, &stdin),
+        timeout: timeout(s, step)?,
+        stream,
+        keep: !stream || step.contains_key(&Yaml::from_str("as")),
+    };
+
+    let output = pipeline(commands, [&stdin, &stdout, &stderr], opts, &mut s.writer)
+        .map_err(|e| error(&line, e))?;
+    finish(s, step, &line, [redirect::var(&stdout), redirect::var(&stderr)], output)
+}
+
+// Binds the output of finished commands to `as`, and to the variables that stdout and stderr are
+// redirected to, if any, then checks it, if asked to.
+pub fn finish(
+    s: &mut Script,
+    step: &Hash,
+    line: &str,
+    vars: [Option<String>; 2],
+    output: Output,
+) -> Result<(), ScriptError> {
+    let check = flag(step, "check")?;
+
+    for (var, bytes) in vars.into_iter().zip([&output.stdout, &output.stderr]) {
+        if let Some(name) = var {
+            s.binding.set_var(name, Yaml::String(process::text(bytes)));
+        }
+    }
+
+    let name = Binding::entry_to_name(step, "as")?.unwrap_or("_");
+    let (status, stderr) = (output.status, process::text(&output.stderr));
+    s.binding.set_var(name, result(output));
+
+    if check && !status.success() {
+        let msg = match tail(stderr.trim()) {
+            "" => format!("{line}: {status}"),
+            tail => format!("{line}: {status}: {tail}"),
+        };
+        let err = ScriptError::new(ErrorKind::Exec, msg);
+        return Err(ScriptError {
+            code: status.code(),
+            ..err
+        });
+    }
+
+    Ok(())
+}
+
+// How much of the stderr of a failed command goes in its error, in characters.
+const MAX_STDERR: usize = 200;
+
+// The end of the stderr of a failed command, which is where the reason usually is.
+fn tail(text: &str) -> &str {
+    match text.char_indices().rev().nth(MAX_STDERR - 1) {
+        Some((i, _)) => &text[i..],
+        None => text,
+    }
+}
+
+pub fn redirects(s: &mut Script, step: &Hash) -> Result<[Option<Redirect>; 3], ScriptError> {
+    Ok([
+        Redirect::parse(s, step, "stdin")?,
+        Redirect::parse(s, step, "stdout")?,
+        Redirect::parse(s, step, "stderr")?,
+    ])
+}
+
+// The input to feed the commands, if any.
+pub fn input(s: &Script, stdin: &Option<Redirect>) -> Option<String> {
+    match stdin {
+        Some(Redirect::Var(name)) => Some(yaml_to_string(s, &s.binding.var(name))),
+        Some(Redirect::Text(text)) => Some(text.clone()),
+        _ => None,
+    }
+}
+
+pub fn flag(step: &Hash, key: &str) -> Result<bool, ScriptError> {
+    match step.get(&Yaml::from_str(key)) {
+        Some(Yaml::Boolean(b)) => Ok(*b),
+        Some(_) => Err(ScriptError::type_mismatch("boolean", key)),
+        None => Ok(false),
+    }
+}
+
+pub fn timeout(s: &mut Script, step: &Hash) -> Result<Option<Duration>, ScriptError> {
+    let val = match step.get(&Yaml::from_str("timeout")) {
+        Some(yaml) => s.eval_to_yaml(yaml)?,
+        None => return Ok(None),
+    };
+    let secs = val.as_f64().or(val.as_i64().map(|n| n as f64)).unwrap_or(-1.0);
+
+    Duration::try_from_secs_f64(secs)
+        .map(Some)
+        .map_err(|_| ScriptError::type_mismatch("number of seconds", "timeout"))
+}
+
+pub fn result(output: Output) -> Yaml {
+    let code = output.status.code().map_or(Yaml::Null, |c| Yaml::Integer(c.into()));
+    let mut hash = Hash::new();
+    hash.insert(Yaml::from_str("code"), code);
+    hash.insert(Yaml::from_str("stdout"), Yaml::String(process::text(&output.stdout)));
+    hash.insert(Yaml::from_str("stderr"), Yaml::String(process::text(&output.stderr)));
+    hash.insert(Yaml::from_str("ok"), Yaml::Boolean(output.status.success()));
+
+    Yaml::Hash(hash)
+}
+
+//-----------------------------------------------------------------------------
+
+// Builds the commands of an exec step (the command, and those it pipes to), with their environment
+// and working directory. Returns them, and the pipeline as a line for messages.
+pub fn commands(
+    s: &mut Script,
+    line: String,
+    step: &Hash,
+) -> Result<(Vec<Command>, String), ScriptError> {
+    let mut commands = vec![command(s, &line, step)?];
+    let mut lines = vec![line];
+
+    for yaml in Binding::entry_to_list_or_empty(step, "pipe")? {
+        let line = s.eval_to_string(yaml)?;
+        commands.push(program(words::split(&line)?)?);
+        lines.push(line);
+    }
+
+    let env = match step.get(&Yaml::from_str("env")) {
+        Some(Yaml::Hash(hash)) => s.eval_to_frame(hash, "env")?,
+        Some(_) => return Err(ScriptError::type_mismatch("mapping", "env")),
+        None => Frame::new(),
+    };
+    // The environment of the script, which env steps change, if it's bound.
+    let base = match s.binding.vars.get("env") {
+        Some(Yaml::Hash(base)) => Some(base.clone()),
+        _ => None,
+    };
+    let clear_env = flag(step, "clear_env")?;
+    let cwd = step.get(&Yaml::from_str("cwd")).map(|y| s.eval_to_string(y)).transpose()?;
+
+    for command in &mut commands {
+        if let Some(base) = &base {
+            command.env_clear();
+            command
+                .envs(base.iter().filter_map(|(name, val)| Some((name.as_str()?, val.as_str()?))));
+        }
+
+        if clear_env {
+            command.env_clear();
+        }
+
+        // A null value removes the variable.
+        for (name, val) in &env {
+            match val {
+                Yaml::Null => command.env_remove(name),
+                _ => command.env(name, yaml_to_string(s, val)),
+            };
+        }
+
+        if let Some(cwd) = &cwd {
+            command.current_dir(cwd);
+        }
+    }
+
+    Ok((commands, lines.join(" | ")))
+}
+
+fn command(s: &mut Script, line: &str, step: &Hash) -> Result<Command, ScriptError> {
+    let words = match (flag(step, "shell")?, args(s, step)?) {
+        (true, args) => {
+            let shell = ["sh", "-c", line, "sh"].map(String::from);
+            [shell.to_vec(), args.unwrap_or_default()].concat()
+        }
+        (false, Some(args)) => [vec![line.into()], args].concat(),
+        (false, None) => words::split(line)?,
+    };
+
+    program(words)
+}
+
+fn program(words: Vec<String>) -> Result<Command, ScriptError> {
+    let name = words.first().filter(|w| !w.is_empty());
+    let mut command =
+        Command::new(name.ok_or_else(|| ScriptError::new(ErrorKind::Exec, "empty command"))?);
+    command.args(&words[1..]);
+
+    Ok(command)
 }
 
-fn text(output: Output) -> String {
-    String::from_utf8(bytes(output)).unwrap().strip_suffix("\n").unwrap().to_string()
+fn args(s: &mut Script, step: &Hash) -> Result<Option<Vec<String>>, ScriptError> {
+    let list = match step.get(&Yaml::from_str("args")) {
+        Some(Yaml::Array(list)) => {
+            return list.iter().map(|a| s.eval_to_string(a)).collect::<Result<_, _>>().map(Some);
+        }
+        Some(yaml) => s.eval_to_yaml(yaml)?,
+        None => return Ok(None),
+    };
+
+    match list {
+        Yaml::Array(list) => Ok(Some(list.iter().map(|a| yaml_to_string(s, a)).collect())),
+        _ => Err(ScriptError::type_mismatch("list", "args")),
+    }
 }
 
-fn bytes(output: Output) -> Vec<u8> {
-    ternary!(output.status.code() == Some(0), output.stdout, output.stderr)
+pub fn yaml_to_string(s: &Script, yaml: &Yaml) -> String {
+    s.binding.value_to_string(s.binding.yaml_to_value(yaml))
 }
 
-fn command(expr: String) -> Command {
-    let tokens: Vec<&str> = expr.split_whitespace().collect();
-    let mut command = Command::new(tokens[0]);
-    command.args(ternary!(tokens.len() > 1, &tokens[1..], &[]));
+//-----------------------------------------------------------------------------
 
-    command
+// How to run the commands of an exec step, besides where their streams go.
+struct Options {
+    input: Option<String>,
+    timeout: Option<Duration>,
+    // Whether output that isn't redirected is written as it comes, and if so, whether it's also
+    // kept for the result.
+    stream: bool,
+    keep: bool,
 }
 
-fn var(step: &Hash) -> Option<String> {
-    step.get(&Yaml::from_str("as")).map(|e| e.as_str().expect("expected string").to_string())
+// Runs the commands with the stdout of each piped to the stdin of the next, and returns the output
+// of the last one, once they've all finished.
+fn pipeline(
+    commands: Vec<Command>,
+    redirects: [&Option<Redirect>; 3],
+    opts: Options,
+    writer: &mut Writer,
+) -> io::Result<Output> {
+    let [_, stdout, stderr] = redirects;
+    let mut children = process::spawn(commands, redirects, Stdio::piped)?;
+    let input = process::feed(&mut children, opts.input);
+
+    let (tx, rx) = mpsc::channel();
+    let tee = |redirect: &Option<Redirect>| (opts.stream && redirect.is_none()).then(|| tx.clone());
+    let last = children.last_mut().ok_or(io::ErrorKind::InvalidInput)?;
+    let out = process::read(last.stdout.take(), tee(stdout), false, opts.keep);
+    let err = process::read(last.stderr.take(), tee(stderr), true, opts.keep);
+    drop(tx);
+
+    let status = process::wait(&mut children, opts.timeout, rx, writer)?;
+
+    // A command doesn't have to read all of its input.
+    if let Some(input) = input {
+        _ = input.join();
+    }
+
+    Ok(Output {
+        status,
+        stdout: out.join().unwrap_or_default(),
+        stderr: err.join().unwrap_or_default(),
+    })
+}
+
+pub fn error(line: &str, err: io::Error) -> ScriptError {
+    ScriptError::new(ErrorKind::Exec, format!("{line}: {err}"))
 }
 
 //=============================================================================
 
 #[cfg(test)]
 mod tests {
-    use super::super::binding::Binding;
+    use super::super::test_util::Dir;
     use super::*;
 
     #[test]
@@ -43,7 +306,11 @@ mod tests {
         let mut script = Script::new(String::new(), None);
 
         super::run(&mut script, &Yaml::from_str("echo 1"), &Hash::new()).unwrap();
-        assert_eq!("1", script.binding.var("_").as_str().unwrap());
+        let res = script.binding.var("_");
+        assert_eq!("1", res["stdout"].as_str().unwrap());
+        assert_eq!("", res["stderr"].as_str().unwrap());
+        assert_eq!(0, res["code"].as_i64().unwrap());
+        assert!(res["ok"].as_bool().unwrap());
     }
 
     #[test]
@@ -52,6 +319,241 @@ mod tests {
         let hash = Binding::hash_from_str("as: a");
 
         super::run(&mut script, &Yaml::from_str("echo 1"), &hash).unwrap();
-        assert_eq!("1", script.binding.var("a").as_str().unwrap());
+        assert_eq!("1", script.binding.var("a")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_failed() {
+        let mut script = Script::new(String::new(), None);
+
+        super::run(&mut script, &Yaml::from_str("ls no-such-file"), &Hash::new()).unwrap();
+        let res = script.binding.var("_");
+        assert_eq!("", res["stdout"].as_str().unwrap());
+        assert!(!res["stderr"].as_str().unwrap().is_empty());
+        assert_ne!(0, res["code"].as_i64().unwrap());
+        assert!(!res["ok"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn run_empty_output() {
+        let mut script = Script::new(String::new(), None);
+
+        super::run(&mut script, &Yaml::from_str("true"), &Hash::new()).unwrap();
+        assert_eq!("", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_check() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("check: true");
+
+        super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap();
+
+        let err = super::run(&mut script, &Yaml::from_str("false"), &hash).unwrap_err();
+        assert_eq!(ErrorKind::Exec, err.kind);
+        assert_eq!(Some(1), err.code);
+        assert_eq!("false: exit status: 1", err.to_string());
+        assert!(!script.binding.var("_")["ok"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn run_check_stderr() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{check: true, as: out}");
+
+        let line = Yaml::from_str("sh -c 'echo bad >&2; exit 2'");
+        let err = super::run(&mut script, &line, &hash).unwrap_err();
+        assert_eq!("sh -c 'echo bad >&2; exit 2': exit status: 2: bad", err.to_string());
+        assert_eq!("bad", script.binding.var("out")["stderr"].as_str().unwrap());
+
+        let text = format!("{}b", "a".repeat(MAX_STDERR));
+        assert_eq!(format!("{}b", "a".repeat(MAX_STDERR - 1)), tail(&text));
+    }
+
+    #[test]
+    fn run_check_not_boolean() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("check: yes");
+
+        let err = super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap_err();
+        assert_eq!(ErrorKind::TypeMismatch, err.kind);
+    }
+
+    #[test]
+    fn run_quoted() {
+        let mut script = Script::new(String::new(), None);
+
+        super::run(&mut script, &Yaml::from_str(r#"echo "a  b" 'c'"#), &Hash::new()).unwrap();
+        assert_eq!("a  b c", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_args() {
+        let mut script = Script::new(String::new(), None);
+        script.binding.set_var("x", Yaml::from_str("a  b"));
+
+        for e in ["args: ['${x}', 1]", "args: '${array(x, 1)}'"] {
+            super::run(&mut script, &Yaml::from_str("echo"), &Binding::hash_from_str(e)).unwrap();
+            assert_eq!("a  b 1", script.binding.var("_")["stdout"].as_str().unwrap(), "{e}");
+        }
+    }
+
+    #[test]
+    fn run_shell() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{shell: true, args: [b]}");
+
+        super::run(&mut script, &Yaml::from_str("echo a $1 | tr ab xy"), &hash).unwrap();
+        assert_eq!("x y", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_pipe() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("pipe: ['tr a b', 'tr b c']");
+
+        super::run(&mut script, &Yaml::from_str("echo a"), &hash).unwrap();
+        assert_eq!("c", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_pipe_missing() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("pipe: [no-such-command]");
+
+        let err = super::run(&mut script, &Yaml::from_str("echo a"), &hash).unwrap_err();
+        assert!(err.to_string().starts_with("echo a | no-such-command: "));
+    }
+
+    #[test]
+    fn run_redirect_var() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{stdin: {var: a}, stdout: {var: b}, stderr: {var: c}}");
+        script.binding.set_var("a", Yaml::from_str("x\ny\n"));
+
+        super::run(&mut script, &Yaml::from_str("sort -r"), &hash).unwrap();
+        assert_eq!("y\nx", script.binding.var("b").as_str().unwrap());
+        assert_eq!("", script.binding.var("c").as_str().unwrap());
+    }
+
+    #[test]
+    fn run_redirect_file() {
+        let mut script = Script::new(String::new(), None);
+        let dir = Dir::new("exec", &[]);
+        script.binding.set_var("path", Yaml::String(dir.path("out")));
+
+        for (line, redirect) in [
+            ("echo a", "stdout: ${path}"),
+            ("echo b", "stdout: {file: '${path}', append: true}"),
+            ("cat", "{stdin: {file: '${path}'}, stderr: /dev/null}"),
+        ] {
+            super::run(&mut script, &Yaml::from_str(line), &Binding::hash_from_str(redirect))
+                .unwrap();
+        }
+
+        assert_eq!("a\nb", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_stdin() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("stdin: '${1 + 1}'");
+
+        super::run(&mut script, &Yaml::from_str("cat"), &hash).unwrap();
+        assert_eq!("2", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_env() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{env: {A: '${1 + 1}', HOME: null}, shell: true}");
+
+        super::run(&mut script, &Yaml::from_str("echo $A.$HOME."), &hash).unwrap();
+        assert_eq!("2..", script.binding.var("_")["stdout"].as_str().unwrap());
+
+        let hash = Binding::hash_from_str("{env: {A: 1}, clear_env: true}");
+        super::run(&mut script, &Yaml::from_str("env"), &hash).unwrap();
+        assert_eq!("A=1", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_cwd() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{cwd: /, pipe: [cat]}");
+
+        super::run(&mut script, &Yaml::from_str("pwd"), &hash).unwrap();
+        assert_eq!("/", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_timeout() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{timeout: 0.1, pipe: [cat]}");
+
+        let err = super::run(&mut script, &Yaml::from_str("sleep 5"), &hash).unwrap_err();
+        assert_eq!(ErrorKind::Exec, err.kind);
+        assert_eq!("sleep 5 | cat: timed out after 0.1s", err.to_string());
+
+        let hash = Binding::hash_from_str("timeout: 5");
+        super::run(&mut script, &Yaml::from_str("echo 1"), &hash).unwrap();
+
+        let hash = Binding::hash_from_str("timeout: -1");
+        let err = super::run(&mut script, &Yaml::from_str("echo 1"), &hash).unwrap_err();
+        assert_eq!("expected number of seconds for 'timeout'", err.to_string());
+    }
+
+    #[test]
+    fn run_stream() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{stream: true, shell: true}");
+
+        super::run(&mut script, &Yaml::from_str("echo a; echo b >&2; echo c"), &hash).unwrap();
+        assert_eq!(3, script.writer.log.len());
+        assert_eq!(
+            vec!["a", "c"],
+            script.writer.log.iter().filter(|l| *l != "b").collect::<Vec<_>>()
+        );
+        assert_eq!("", script.binding.var("_")["stdout"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_stream_as() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{stream: true, stderr: {var: e}, as: a}");
+
+        super::run(&mut script, &Yaml::from_str("ls . no-such-file"), &hash).unwrap();
+        assert!(!script.writer.log.is_empty());
+        assert_eq!(
+            script.writer.log.join("\n"),
+            script.binding.var("a")["stdout"].as_str().unwrap()
+        );
+        assert!(!script.binding.var("e").as_str().unwrap().is_empty());
+    }
+
+    #[test]
+    fn run_stream_timeout() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{stream: true, timeout: 0.2, shell: true}");
+
+        let err = super::run(&mut script, &Yaml::from_str("echo a; sleep 5"), &hash).unwrap_err();
+        assert_eq!("echo a; sleep 5: timed out after 0.2s", err.to_string());
+        assert_eq!(vec!["a"], script.writer.log);
+    }
+
+    #[test]
+    fn run_empty() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Yaml::from_str("''"), &Hash::new()).unwrap_err();
+        assert_eq!("empty command", err.to_string());
+    }
+
+    #[test]
+    fn run_missing() {
+        let mut script = Script::new(String::new(), None);
+
+        let err =
+            super::run(&mut script, &Yaml::from_str("no-such-command"), &Hash::new()).unwrap_err();
+        assert_eq!(ErrorKind::Exec, err.kind);
     }
 }
@@ -1,16 +1,9 @@
-use super::Script;
-use std::{io::Error, process::exit};
+use super::{Flow, Script, ScriptError};
 use yaml_rust2::Yaml;
 
 // - exit: <number>
-pub fn run(s: &Script, code: &Yaml) -> Result<(), Error> {
-    run_step(s, code, exit)
-}
-
-#[allow(unreachable_code)]
-fn run_step(s: &Script, code: &Yaml, halt: fn(i32) -> !) -> Result<(), Error> {
-    halt(s.binding.eval_to_i32(code));
-    Ok(())
+pub fn run(s: &mut Script, code: &Yaml) -> Result<Flow, ScriptError> {
+    Ok(Flow::Exit(s.eval_to_i32(code)?))
 }
 
 //=============================================================================
@@ -20,11 +13,27 @@ mod tests {
     use super::*;
 
     #[test]
-    #[should_panic]
-    fn run_step() {
-        let script = Script::new(String::new(), None);
-        let halt = |code: i32| -> ! { panic!("{code}") };
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+
+        let flow = super::run(&mut script, &Yaml::from_str("1")).unwrap();
+        assert_eq!(Flow::Exit(1), flow);
+    }
+
+    #[test]
+    fn run_not_number() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Yaml::from_str("foo")).unwrap_err();
+        assert_eq!(super::super::ErrorKind::TypeMismatch, err.kind);
+    }
+
+    #[test]
+    fn run_out_of_range() {
+        let mut script = Script::new(String::new(), None);
 
-        super::run_step(&script, &Yaml::from_str("1"), halt).unwrap();
+        let err = super::run(&mut script, &Yaml::from_str("4294967296")).unwrap_err();
+        assert_eq!(super::super::ErrorKind::TypeMismatch, err.kind);
+        assert_eq!("number out of range: 4294967296", err.to_string());
     }
 }
@@ -0,0 +1,96 @@
+use super::{ErrorKind, Script, ScriptError};
+use yaml_rust2::Yaml;
+
+// How a list of steps ended. Loops act on breaks and continues meant for them, and pass the
+// others on to outer loops. Returns are taken by the call they return from, and exits end the
+// script.
+#[derive(Debug, PartialEq)]
+pub enum Flow {
+    Next,
+    Break {
+        label: Option<String>,
+        message: String,
+    },
+    Continue {
+        label: Option<String>,
+    },
+    Return(Yaml),
+    Exit(i32),
+}
+
+impl Flow {
+    // Unlabeled breaks and continues are meant for the innermost loop.
+    pub fn is_for(&self, label: Option<&str>) -> bool {
+        match self {
+            Flow::Break { label: target, .. } | Flow::Continue { label: target } => {
+                target.is_none() || target.as_deref() == label
+            }
+            _ => false,
+        }
+    }
+
+    // The error for a break or continue that no loop was there to catch.
+    pub fn error(&self) -> ScriptError {
+        let msg = match self {
+            Flow::Break {
+                label: Some(label), ..
+            }
+            | Flow::Continue { label: Some(label) } => {
+                format!("no loop labeled '{label}'")
+            }
+            Flow::Break { .. } => "'break' outside of a loop".into(),
+            Flow::Continue { .. } => "'continue' outside of a loop".into(),
+            flow => format!("unexpected {flow:?}"),
+        };
+
+        ScriptError::new(ErrorKind::Flow, msg)
+    }
+}
+
+// Runs the steps of a loop once. Returns the flow to end the loop with, if it should end.
+pub fn run_loop_steps(
+    s: &mut Script,
+    steps: &[Yaml],
+    label: Option<&str>,
+) -> Result<Option<Flow>, ScriptError> {
+    let flow = s.run_steps(steps)?;
+
+    Ok(match flow {
+        Flow::Next => None,
+        Flow::Continue { .. } if flow.is_for(label) => None,
+        Flow::Break { .. } if flow.is_for(label) => Some(Flow::Next),
+        _ => Some(flow),
+    })
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brk(label: Option<&str>) -> Flow {
+        Flow::Break {
+            label: label.map(String::from),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn is_for() {
+        assert!(brk(None).is_for(Some("outer")));
+        assert!(brk(Some("outer")).is_for(Some("outer")));
+        assert!(!brk(Some("outer")).is_for(None));
+        assert!(!Flow::Continue {
+            label: Some("outer".into())
+        }
+        .is_for(Some("inner")));
+    }
+
+    #[test]
+    fn error() {
+        assert_eq!("'break' outside of a loop", brk(None).error().to_string());
+        assert_eq!("no loop labeled 'outer'", brk(Some("outer")).error().to_string());
+        assert_eq!(ErrorKind::Flow, Flow::Continue { label: None }.error().kind);
+    }
+}
@@ -0,0 +1,69 @@
+use eval::{to_value, Error, Expr, Value};
+
+// Adds functions to an expression, on top of the eval built-ins (min, max, len, is_empty, array).
+pub fn add(expr: Expr) -> Expr {
+    expr.function("range", range).function("split", split).function("keys", keys)
+}
+
+// range(<end>) | range(<start>, <end>)
+fn range(args: Vec<Value>) -> Result<Value, Error> {
+    let nums: Option<Vec<i64>> = args.iter().map(|v| v.as_i64()).collect();
+
+    match nums.as_deref() {
+        Some(&[end]) => Ok(to_value((0..end).collect::<Vec<i64>>())),
+        Some(&[start, end]) => Ok(to_value((start..end).collect::<Vec<i64>>())),
+        _ => Err(error("range", "expected (end) or (start, end)", &args)),
+    }
+}
+
+// split(<string>) | split(<string>, <separator>)
+fn split(args: Vec<Value>) -> Result<Value, Error> {
+    match &args[..] {
+        [Value::String(s)] => Ok(to_value(s.lines().collect::<Vec<&str>>())),
+        [Value::String(s), Value::String(sep)] => Ok(to_value(s.split(sep).collect::<Vec<&str>>())),
+        _ => Err(error("split", "expected (string) or (string, separator)", &args)),
+    }
+}
+
+// keys(<map>)
+fn keys(args: Vec<Value>) -> Result<Value, Error> {
+    match &args[..] {
+        [Value::Object(map)] => Ok(to_value(map.keys().collect::<Vec<&String>>())),
+        _ => Err(error("keys", "expected (map)", &args)),
+    }
+}
+
+fn error(name: &str, expected: &str, args: &[Value]) -> Error {
+    Error::Custom(format!("{name}(): {expected}, found {}", to_value(args)))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn exec(text: &str) -> Result<Value, Error> {
+        add(Expr::new(text)).value("s", "a\nb").value("m", json!({"x": 1})).exec()
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(to_value([0, 1, 2]), exec("range(3)").unwrap());
+        assert_eq!(to_value([1, 2]), exec("range(1, 3)").unwrap());
+        assert!(exec("range('a')").is_err());
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(to_value(["a", "b"]), exec("split(s)").unwrap());
+        assert_eq!(to_value(["1", "2"]), exec("split('1,2', ',')").unwrap());
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(to_value(["x"]), exec("keys(m)").unwrap());
+        assert!(exec("keys(s)").is_err());
+    }
+}
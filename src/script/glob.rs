@@ -0,0 +1,131 @@
+use regex::Regex;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Paths of the files that match a pattern, sorted. `*` and `?` match any characters, or one, in a
+// file or directory name, and `**/` matches any number of directories. Hidden files (those whose
+// names start with a dot) are only matched by name. A pattern without any of those is just a path.
+pub fn files(pattern: &str) -> Vec<PathBuf> {
+    if !pattern.contains(['*', '?']) {
+        let path = PathBuf::from(pattern);
+        return if path.exists() {
+            vec![path]
+        } else {
+            Vec::new()
+        };
+    }
+
+    // Only the directory before the first wildcard needs to be searched.
+    let parts: Vec<&str> = pattern.split('/').collect();
+    let literal = parts.iter().take_while(|p| !p.contains(['*', '?'])).count();
+    let base = parts[..literal].join("/");
+    let re = regex(pattern);
+    let mut files = Vec::new();
+
+    match base.as_str() {
+        "" if pattern.starts_with('/') => walk(Path::new("/"), &mut files),
+        "" => walk(Path::new("."), &mut files),
+        base => walk(Path::new(base), &mut files),
+    }
+
+    let mut files: Vec<PathBuf> = files
+        .into_iter()
+        .map(|f| f.strip_prefix(".").map(Path::to_path_buf).unwrap_or(f))
+        .filter(|f| f.to_str().is_some_and(|f| re.is_match(f)))
+        .collect();
+    files.sort();
+
+    files
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => walk(&path, files),
+            Ok(_) => files.push(path),
+            Err(_) => (),
+        }
+    }
+}
+
+fn regex(pattern: &str) -> Regex {
+    let mut re = String::from("^");
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    re.push('$');
+    Regex::new(&re).unwrap()
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::Dir;
+
+    #[test]
+    fn files() {
+        let paths = ["a.rs", "b.txt", "src/c.rs", "src/d/e.rs", ".hidden/f.rs"];
+        let dir = Dir::new("glob", &paths.map(|p| (p, "")));
+
+        let names = |pattern: &str| -> Vec<String> {
+            super::files(&dir.path(pattern))
+                .iter()
+                .map(|f| f.strip_prefix(&dir.0).unwrap().display().to_string())
+                .collect()
+        };
+
+        assert_eq!(vec!["a.rs"], names("*.rs"));
+        assert_eq!(vec!["a.rs", "src/c.rs", "src/d/e.rs"], names("**/*.rs"));
+        assert_eq!(vec!["src/c.rs", "src/d/e.rs"], names("src/**"));
+        assert_eq!(vec!["b.txt"], names("?.txt"));
+        assert_eq!(vec![".hidden/f.rs"], names(".hidden/*.rs"));
+        assert_eq!(vec!["b.txt"], names("b.txt"));
+        assert!(names("no-such-file").is_empty());
+    }
+
+    #[test]
+    fn regex() {
+        for e in [
+            ("*.rs", "a.rs", true),
+            ("*.rs", "a/b.rs", false),
+            ("**/*.rs", "a.rs", true),
+            ("**/*.rs", "a/b/c.rs", true),
+            ("a/**", "a/b/c", true),
+            ("a?c", "abc", true),
+            ("a?c", "a/c", false),
+            ("a.c", "abc", false),
+        ] {
+            assert_eq!(e.2, super::regex(e.0).is_match(e.1), "{e:?}");
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use super::doc::Doc;
+use super::params::{self, Param};
+use super::{task, ErrorKind, Flow, Frame, Script, ScriptError};
+use yaml_rust2::{
+    yaml::{Array, Hash},
+    Yaml,
+};
+
+// The header of a script, a mapping in its first document:
+//
+// [help: <string>]
+// [params: <params>]
+//
+// The params are the options of the script, e.g. `--target=prod` or `--target prod` for `target`,
+// and `--dry-run` for a boolean `dry_run`. They're checked like the arguments of a call, and bound
+// as variables (but not `env`, `args` or `opts`, which are the script's), and as the `opts` map,
+// with the other arguments as the `args` list. With `--help`, the usage of the script is written
+// instead of running it, with its named documents and task steps as tasks.
+pub fn run(
+    s: &mut Script,
+    header: &Hash,
+    docs: &[Doc],
+    args: &[String],
+) -> Result<Flow, ScriptError> {
+    check_keys(header)?;
+
+    let params = params::parse(header)?.unwrap_or_default();
+
+    if let Some(param) = params.iter().find(|p| RESERVED.contains(&p.name.as_str())) {
+        let msg = format!("'{}' can't be a param of the script", param.name);
+        return Err(ScriptError::new(ErrorKind::Parse, msg));
+    }
+
+    let help = match header.get(&Yaml::from_str("help")) {
+        Some(Yaml::String(help)) => Some(help.as_str()),
+        Some(_) => return Err(ScriptError::type_mismatch("string", "help")),
+        None => None,
+    };
+
+    if args.iter().take_while(|a| *a != "--").any(|a| a == "--help") {
+        s.writer.write(usage(&s.path, help, &params, docs));
+        return Ok(Flow::Exit(0));
+    }
+
+    let (opts, list) = parse_args(&params, args)?;
+    let opts = params::bind(s, "script", &params, opts)?;
+    let mut hash = Hash::new();
+
+    for (name, val) in opts {
+        hash.insert(Yaml::String(name.clone()), val.clone());
+        s.binding.set_var(name, val);
+    }
+
+    s.binding.set_var("opts", Yaml::Hash(hash));
+    s.binding.set_var("args", Yaml::Array(list));
+    Ok(Flow::Next)
+}
+
+// The variables that are bound for every script.
+const RESERVED: [&str; 3] = ["env", "args", "opts"];
+
+// A header with the keys of a named document is one that's missing its steps.
+fn check_keys(header: &Hash) -> Result<(), ScriptError> {
+    let mut named = false;
+
+    for key in header.keys() {
+        match key.as_str() {
+            Some("help" | "params") => (),
+            Some("name" | "description" | "requires") => named = true,
+            Some(key) => {
+                return Err(ScriptError::new(ErrorKind::Parse, format!("unknown key '{key}'")))
+            }
+            None => return Err(ScriptError::type_mismatch("string", "key")),
+        }
+    }
+
+    match named {
+        true => Err(ScriptError::missing_key("do")),
+        false => Ok(()),
+    }
+}
+
+// Splits the arguments into options, with values of the types of their params, and the rest.
+fn parse_args(params: &[Param], args: &[String]) -> Result<(Frame, Array), ScriptError> {
+    let mut opts = Frame::new();
+    let mut list = Array::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let opt = match arg.strip_prefix("--") {
+            Some("") => {
+                list.extend(args.by_ref().map(|a| Yaml::String(a.clone())));
+                break;
+            }
+            Some(opt) => opt,
+            None => {
+                list.push(Yaml::String(arg.clone()));
+                continue;
+            }
+        };
+
+        let (key, val) = match opt.split_once('=') {
+            Some((key, val)) => (key, Some(val)),
+            None => (opt, None),
+        };
+        let name = key.replace('-', "_");
+        let param = params.iter().find(|p| p.name == name).ok_or_else(|| {
+            ScriptError::new(ErrorKind::Argument, format!("unknown option '--{key}'"))
+        })?;
+
+        let val = match (val, param.kind.as_str()) {
+            (Some(val), "string") => Yaml::String(val.into()),
+            (Some(val), _) => Yaml::from_str(val),
+            (None, "boolean") => Yaml::Boolean(true),
+            (None, kind) => {
+                let val = args.next().ok_or_else(|| {
+                    ScriptError::new(ErrorKind::Argument, format!("missing value for '--{key}'"))
+                })?;
+                match kind {
+                    "string" => Yaml::String(val.clone()),
+                    _ => Yaml::from_str(val),
+                }
+            }
+        };
+        opts.insert(name, val);
+    }
+
+    Ok((opts, list))
+}
+
+fn usage(path: &str, help: Option<&str>, params: &[Param], docs: &[Doc]) -> String {
+    let mut lines = vec![format!("usage: ys {path} [<option>...] [<arg>...]")];
+
+    if let Some(help) = help {
+        lines.extend(["".into(), help.trim_end().into()]);
+    }
+
+    lines.extend(["".into(), "options:".into()]);
+    let opts: Vec<_> = params
+        .iter()
+        .map(|p| {
+            let opt = format!("--{}", p.name.replace('_', "-"));
+            let opt = match p.kind.as_str() {
+                "boolean" => opt,
+                kind => format!("{opt} <{kind}>"),
+            };
+            (opt, describe(p))
+        })
+        .chain([("--help".into(), "show this help".into())])
+        .collect();
+    let width = opts.iter().map(|o| o.0.len()).max().unwrap_or_default();
+
+    for (opt, text) in opts {
+        lines.push(format!("  {opt:width$}  {text}").trim_end().into());
+    }
+
+    let tasks: Vec<_> = docs
+        .iter()
+        .filter_map(|d| Some((d.name.as_deref()?, d.description.as_deref())))
+        .chain(task::list(docs))
+        .collect();
+    let width = tasks.iter().map(|t| t.0.len()).max().unwrap_or_default();
+
+    if !tasks.is_empty() {
+        lines.extend(["".into(), "tasks (--task <name>):".into()]);
+    }
+
+    for (name, description) in tasks {
+        let text = description.unwrap_or_default();
+        lines.push(format!("  {name:width$}  {text}").trim_end().into());
+    }
+
+    lines.join("\n")
+}
+
+// The help of a param, and its default, or whether it's required.
+fn describe(param: &Param) -> String {
+    let extra = match &param.default {
+        Some(Yaml::String(s) | Yaml::Real(s)) => Some(format!("(default: {s})")),
+        Some(Yaml::Integer(n)) => Some(format!("(default: {n})")),
+        Some(Yaml::Boolean(b)) => Some(format!("(default: {b})")),
+        Some(_) => None,
+        None if param.required => Some("(required)".into()),
+        None => None,
+    };
+
+    [param.help.clone(), extra].into_iter().flatten().collect::<Vec<_>>().join(" ")
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::Binding;
+    use super::*;
+
+    const HEADER: &str = "
+help: Deploys the app.
+params:
+  - {name: target, type: string, help: where to deploy}
+  - {name: count, type: integer, default: 1}
+  - {name: dry_run, type: boolean, default: false}
+";
+
+    fn run(args: &[&str]) -> Result<Script, ScriptError> {
+        let mut script = Script::new("deploy.ys".into(), Some(Vec::new()));
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        super::run(&mut script, &Binding::hash_from_str(HEADER), &[], &args).map(|_| script)
+    }
+
+    #[test]
+    fn run_opts() {
+        for args in [
+            vec![
+                "--target",
+                "prod",
+                "a",
+                "--count=3",
+                "--dry-run",
+                "--",
+                "--b",
+            ],
+            vec![
+                "a",
+                "--dry-run",
+                "--count",
+                "3",
+                "--target=prod",
+                "--",
+                "--b",
+            ],
+        ] {
+            let script = run(&args).unwrap();
+            assert_eq!("prod", script.binding.var("target").as_str().unwrap(), "{args:?}");
+            assert_eq!(3, script.binding.var("count").as_i64().unwrap());
+            assert!(script.binding.var("dry_run").as_bool().unwrap());
+            assert_eq!(3, script.binding.var("opts")["count"].as_i64().unwrap());
+
+            let list = script.binding.var("args");
+            assert_eq!(vec![Yaml::from_str("a"), Yaml::from_str("--b")], *list.as_vec().unwrap());
+        }
+    }
+
+    #[test]
+    fn run_defaults() {
+        let script = run(&["--target=1"]).unwrap();
+        assert_eq!("1", script.binding.var("target").as_str().unwrap());
+        assert_eq!(1, script.binding.var("count").as_i64().unwrap());
+        assert!(!script.binding.var("dry_run").as_bool().unwrap());
+    }
+
+    #[test]
+    fn run_errors() {
+        for e in [
+            (vec![], "script: missing argument 'target'"),
+            (vec!["--target=a", "--foo"], "unknown option '--foo'"),
+            (vec!["--target"], "missing value for '--target'"),
+            (vec!["--target=a", "--count=x"], "script: expected integer for 'count', found string"),
+        ] {
+            assert_eq!(e.1, run(&e.0).err().unwrap().to_string(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn run_keys() {
+        let mut script = Script::new(String::new(), None);
+
+        for e in [
+            ("{params: [], steps: []}", "unknown key 'steps'"),
+            ("{name: build, steps: []}", "unknown key 'steps'"),
+            ("{name: build, requires: [a]}", "expected 'do'"),
+            ("{params: [], 1: a}", "expected string for 'key'"),
+            ("{params: [a, env]}", "'env' can't be a param of the script"),
+            ("{params: [{name: opts}]}", "'opts' can't be a param of the script"),
+        ] {
+            let hash = Binding::hash_from_str(e.0);
+            let err = super::run(&mut script, &hash, &[], &[]).unwrap_err();
+            assert_eq!(e.1, err.to_string(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn run_help() {
+        let mut script = Script::new("deploy.ys".into(), Some(Vec::new()));
+        let args = ["--count=x".into(), "--help".into()];
+        let steps = yaml_rust2::YamlLoader::load_from_str(
+            "[{task: lint, description: Checks it., do: []}, {def: f, do: []}, {task: fmt, do: []}]",
+        )
+        .unwrap();
+
+        let docs = [
+            Doc {
+                steps: steps[0].as_vec().unwrap(),
+                ..Doc::default()
+            },
+            Doc {
+                name: Some("build".into()),
+                description: Some("Builds it.".into()),
+                ..Doc::default()
+            },
+            Doc {
+                name: Some("test".into()),
+                ..Doc::default()
+            },
+        ];
+
+        let flow = super::run(&mut script, &Binding::hash_from_str(HEADER), &docs, &args).unwrap();
+        assert_eq!(Flow::Exit(0), flow);
+        assert_eq!(
+            [
+                "usage: ys deploy.ys [<option>...] [<arg>...]",
+                "",
+                "Deploys the app.",
+                "",
+                "options:",
+                "  --target <string>  where to deploy (required)",
+                "  --count <integer>  (default: 1)",
+                "  --dry-run          (default: false)",
+                "  --help             show this help",
+                "",
+                "tasks (--task <name>):",
+                "  build  Builds it.",
+                "  test",
+                "  lint   Checks it.",
+                "  fmt",
+            ]
+            .join("\n"),
+            script.writer.log[0]
+        );
+    }
+}
@@ -1,20 +1,26 @@
-use super::{Binding, Script};
-use std::io::Error;
+use super::{Binding, Flow, Script, ScriptError};
 use ternop::ternary;
 use yaml_rust2::{yaml::Hash, Yaml};
 
 // - if: <condition>
 //   [then: <steps>]
 //   [else: <steps>]
-pub fn run(s: &mut Script, cond: &Yaml, step: &Hash) -> Result<(), Error> {
-    let key = ternary!(s.binding.is_truthy(cond), "then", "else");
-    s.run_steps(&Binding::entry_to_list(step, key))
+//
+// At least one of then and else is needed.
+pub fn run(s: &mut Script, cond: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
+    if !["then", "else"].iter().any(|k| step.contains_key(&Yaml::from_str(k))) {
+        return Err(ScriptError::missing_key("then"));
+    }
+
+    let key = ternary!(s.is_truthy(cond)?, "then", "else");
+    s.run_steps(Binding::entry_to_list_or_empty(step, key)?)
 }
 
 //=============================================================================
 
 #[cfg(test)]
 mod tests {
+    use super::super::ErrorKind;
     use super::*;
 
     #[test]
@@ -34,4 +40,23 @@ mod tests {
         super::run(&mut script, &Yaml::from_str("false"), &hash).unwrap();
         assert_eq!(42, script.binding.var("a").as_i64().unwrap());
     }
+
+    #[test]
+    fn run_missing_else() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("then: [a: 42]");
+
+        super::run(&mut script, &Yaml::from_str("false"), &hash).unwrap();
+        assert_eq!(Yaml::Null, script.binding.var("a"));
+    }
+
+    #[test]
+    fn run_missing_then() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("than: [a: 42]");
+
+        let err = super::run(&mut script, &Yaml::from_str("false"), &hash).unwrap_err();
+        assert_eq!(ErrorKind::MissingKey, err.kind);
+        assert_eq!("expected 'then'", err.to_string());
+    }
 }
@@ -0,0 +1,173 @@
+use super::{def, loader, task, Binding, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - import: <path>
+//   [as: <namespace>]
+//
+// Defines the procs and tasks of another file, relative to the current one, without running its
+// other steps. With `as`, they're defined in a namespace, e.g. `ns.foo`, where they can call each
+// other without it. Files that it imports are imported too, in namespaces of its own.
+pub fn run(s: &mut Script, expr: &Yaml, step: &Hash) -> Result<(), ScriptError> {
+    let path = s.eval_to_string(expr)?;
+    let namespace = Binding::entry_to_name(step, "as")?.map(String::from);
+
+    import(s, &s.resolve(&path), namespace)
+}
+
+fn import(s: &mut Script, path: &str, namespace: Option<String>) -> Result<(), ScriptError> {
+    s.with_file(path, |s, text| {
+        s.with_docs(text, |s, docs| {
+            for (doc, _) in docs {
+                for step in doc.as_vec().into_iter().flatten() {
+                    if let Some(hash) = step.as_hash() {
+                        define(s, hash, namespace.as_deref())
+                            .map_err(|e| e.at(loader::location(&s.marks, hash)))?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    })
+}
+
+// Runs a def, task, or import step of an imported file, and skips any other.
+fn define(s: &mut Script, step: &Hash, namespace: Option<&str>) -> Result<(), ScriptError> {
+    let qualify = |name: &str| match namespace {
+        Some(ns) => format!("{ns}.{name}"),
+        None => name.to_string(),
+    };
+
+    match step.iter().next() {
+        Some((key, val)) if key.as_str() == Some("def") => {
+            let name = qualify(Binding::yaml_to_name(val, "def")?);
+            def::run(s, &Yaml::String(name), step)
+        }
+        Some((key, val)) if key.as_str() == Some("task") => {
+            let name = qualify(Binding::yaml_to_name(val, "task")?);
+            task::run(s, &Yaml::String(name), step)
+        }
+        Some((key, val)) if key.as_str() == Some("import") => {
+            let path = s.eval_to_string(val)?;
+            let inner = Binding::entry_to_name(step, "as")?.map(qualify);
+            import(s, &s.resolve(&path), inner.or(namespace.map(String::from)))
+        }
+        _ => Ok(()),
+    }
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::Dir;
+    use super::*;
+
+    // Writes the files of a test to a new directory, and runs the first one.
+    fn run_files(name: &str, files: &[(&str, &str)]) -> (Result<i32, ScriptError>, Vec<String>) {
+        let dir = Dir::new(name, files);
+        let mut script = dir.script(files[0].0);
+        let res = script.run();
+
+        (res, script.writer.log)
+    }
+
+    #[test]
+    fn run() {
+        let (res, log) = run_files(
+            "import",
+            &[
+                ("main.ys", "- import: lib/a.ys\n- call: hi\n- echo: ${x}\n"),
+                ("lib/a.ys", "- x: 1\n- def: hi\n  do: [echo: hi]\n- echo: no\n"),
+            ],
+        );
+
+        res.unwrap();
+        assert_eq!(vec!["hi", "null"], log);
+    }
+
+    #[test]
+    fn run_as() {
+        let main = "- import: lib/a.ys\n  as: a\n- call: a.hi\n- echo: ${a.twice(2)}\n";
+        let lib = concat!(
+            "- {import: b.ys, as: b}\n",
+            "- {def: hi, do: [echo: '${b.name()}']}\n",
+            "- {def: twice, params: [x], do: [return: '${double(x)}']}\n",
+            "- {def: double, params: [x], do: [return: '${x * 2}']}\n",
+        );
+        let (res, log) = run_files(
+            "import-as",
+            &[
+                ("main.ys", main),
+                ("lib/a.ys", lib),
+                ("lib/b.ys", "- {def: name, do: [return: b]}\n"),
+            ],
+        );
+
+        res.unwrap();
+        assert_eq!(vec!["b", "4"], log);
+    }
+
+    #[test]
+    fn run_unknown() {
+        let (res, _) = run_files(
+            "import-unknown",
+            &[
+                ("main.ys", "- import: lib/a.ys\n  as: a\n- call: hi\n"),
+                ("lib/a.ys", "- def: hi\n  do: []\n"),
+            ],
+        );
+
+        assert!(res.unwrap_err().to_string().ends_with("unknown proc 'hi'"));
+    }
+
+    #[test]
+    fn run_cycle() {
+        let (res, _) = run_files(
+            "import-cycle",
+            &[
+                ("main.ys", "- import: lib/a.ys\n"),
+                ("lib/a.ys", "- import: ../main.ys\n"),
+            ],
+        );
+
+        let err = res.unwrap_err();
+        assert_eq!(super::super::ErrorKind::Flow, err.kind);
+        assert!(err.to_string().contains("cycle: "));
+    }
+
+    #[test]
+    fn run_relative() {
+        let (res, log) = run_files(
+            "import-relative",
+            &[
+                (
+                    "main.ys",
+                    "- import: lib/a.ys
+- call: hi
+- include: b.ys
+",
+                ),
+                (
+                    "b.ys",
+                    "- echo: main
+",
+                ),
+                (
+                    "lib/a.ys",
+                    "- def: hi
+  do: [include: b.ys]
+",
+                ),
+                (
+                    "lib/b.ys",
+                    "- echo: lib
+",
+                ),
+            ],
+        );
+
+        res.unwrap();
+        assert_eq!(vec!["lib", "main"], log);
+    }
+}
@@ -0,0 +1,79 @@
+use super::{Flow, Script, ScriptError};
+use yaml_rust2::Yaml;
+
+// - include: <path>
+//
+// Runs the steps of another file, relative to the current one, as if they were in its place. A
+// break, return, or exit in them applies here too.
+pub fn run(s: &mut Script, expr: &Yaml) -> Result<Flow, ScriptError> {
+    let path = s.eval_to_string(expr)?;
+    let path = s.resolve(&path);
+
+    s.with_file(&path, |s, text| s.run_str(text))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::Dir;
+    use super::super::ErrorKind;
+
+    #[test]
+    fn run() {
+        let dir = Dir::new(
+            "include",
+            &[
+                ("main.ys", "- a: 1\n- include: lib/a.ys\n- echo: ${a}\n"),
+                ("lib/a.ys", "- echo: ${a}\n- include: b.ys\n"),
+                ("lib/b.ys", "help: ignored\n---\n- a: 2\n- return:\n- a: 3\n"),
+            ],
+        );
+        let mut script = dir.script("main.ys");
+
+        assert_eq!(0, script.run().unwrap());
+        assert_eq!(vec!["1"], script.writer.log);
+    }
+
+    #[test]
+    fn run_error() {
+        let dir = Dir::new(
+            "include-error",
+            &[
+                ("main.ys", "- include: a.ys\n"),
+                ("a.ys", "- echo: ok\n- if: 1\n  then: 2\n"),
+            ],
+        );
+        let mut script = dir.script("main.ys");
+
+        let err = script.run().unwrap_err();
+        let path = dir.path("a.ys");
+        assert_eq!(format!("{path}:2:3: expected list for 'then'"), err.to_string());
+    }
+
+    #[test]
+    fn run_cycle() {
+        let dir = Dir::new(
+            "include-cycle",
+            &[
+                ("main.ys", "- include: lib/a.ys\n"),
+                ("lib/a.ys", "- include: ../main.ys\n"),
+            ],
+        );
+        let mut script = dir.script("main.ys");
+
+        let err = script.run().unwrap_err();
+        assert_eq!(ErrorKind::Flow, err.kind);
+        assert!(err.to_string().contains("cycle: "), "{err}");
+        assert!(err.to_string().ends_with("lib/../main.ys"), "{err}");
+    }
+
+    #[test]
+    fn run_missing() {
+        let dir = Dir::new("include-missing", &[("main.ys", "- include: no-such-file.ys\n")]);
+        let mut script = dir.script("main.ys");
+
+        let err = script.run().unwrap_err();
+        assert_eq!(ErrorKind::Io, err.kind);
+    }
+}
@@ -0,0 +1,331 @@
+use super::{call, template, ErrorKind, Flow, Frame, Script, ScriptError};
+use regex::Regex;
+use std::sync::LazyLock;
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// Runs the procs called in the expressions of a value (e.g. `${double(x) + 1}`), and replaces each
+// call with a variable that holds its result. Returns the new value, and those variables.
+pub fn resolve(s: &mut Script, yaml: &Yaml) -> Result<(Yaml, Frame), ScriptError> {
+    let mut calls = Frame::new();
+    let yaml = resolve_yaml(s, yaml, &mut calls)?;
+
+    Ok((yaml, calls))
+}
+
+fn resolve_yaml(s: &mut Script, yaml: &Yaml, calls: &mut Frame) -> Result<Yaml, ScriptError> {
+    match yaml {
+        Yaml::String(text) if !s.binding.procs.is_empty() => {
+            Ok(Yaml::String(resolve_text(s, text, calls)?))
+        }
+        Yaml::Array(list) => {
+            let items: Result<Vec<Yaml>, ScriptError> =
+                list.iter().map(|e| resolve_yaml(s, e, calls)).collect();
+            Ok(Yaml::Array(items?))
+        }
+        Yaml::Hash(hash) => {
+            let mut entries = Hash::new();
+
+            for (key, val) in hash {
+                entries.insert(key.clone(), resolve_yaml(s, val, calls)?);
+            }
+
+            Ok(Yaml::Hash(entries))
+        }
+        _ => Ok(yaml.clone()),
+    }
+}
+
+fn resolve_text(s: &mut Script, text: &str, calls: &mut Frame) -> Result<String, ScriptError> {
+    let mut buf = String::new();
+    let mut last = 0;
+
+    for range in template::expressions(text)? {
+        buf.push_str(&text[last..range.start]);
+        buf.push_str(&resolve_token(s, &text[range.clone()], calls)?);
+        last = range.end;
+    }
+
+    buf.push_str(&text[last..]);
+    Ok(buf)
+}
+
+// A name followed by `(`, e.g. `double(` or `ns.double (`.
+static CALL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[a-zA-Z_][a-zA-Z0-9_]*(\.[a-zA-Z_][a-zA-Z0-9_]*)*\s*\(").unwrap()
+});
+
+// The calls are all run first, in order, so a call can't be where the expression might not reach
+// it, after `&&` or `||`.
+fn resolve_token(s: &mut Script, token: &str, calls: &mut Frame) -> Result<String, ScriptError> {
+    let mut buf = token.to_string();
+    let mut pos = 0;
+    let mut scanner = Scanner::default();
+
+    while let Some(m) = CALL.find_at(&buf, pos) {
+        let name = m.as_str().trim_end_matches('(').trim_end().to_string();
+        let (start, open) = (m.start(), m.end() - 1);
+        pos = m.end();
+        scanner.scan(&buf[scanner.pos..start]);
+
+        if scanner.quotes.quote.is_some() || s.binding.proc_name(&name).is_none() {
+            continue;
+        }
+
+        if scanner.is_conditional() {
+            let msg = format!("{token}: {name}() can't be called after && or ||");
+            return Err(ScriptError::new(ErrorKind::Expression, msg));
+        }
+
+        let close = closing_paren(&buf, open).ok_or_else(|| {
+            ScriptError::new(ErrorKind::Expression, format!("{token}: unpaired brackets"))
+        })?;
+        let val = run(s, &name, &split_args(&buf[open + 1..close]))?;
+        let var = format!("__call{}", calls.len());
+        calls.insert(var.clone(), val);
+
+        // The call is replaced by a name, which doesn't change what's scanned.
+        buf.replace_range(start..=close, &var);
+        pos = start + var.len();
+        scanner.pos = pos;
+    }
+
+    Ok(buf)
+}
+
+fn run(s: &mut Script, name: &str, args: &[&str]) -> Result<Yaml, ScriptError> {
+    let params = call::param_names(s, name)?;
+
+    if args.len() > params.len() {
+        let msg = format!("{name}() takes {} argument(s), found {}", params.len(), args.len());
+        return Err(ScriptError::new(ErrorKind::Expression, msg));
+    }
+
+    let mut frame = Frame::new();
+
+    for (param, arg) in params.into_iter().zip(args) {
+        let val = s.eval_to_yaml(&Yaml::String(format!("${{{arg}}}")))?;
+        frame.insert(param, val);
+    }
+
+    // There's no step for an exit to end the script from here.
+    match call::run_proc(s, name, frame)? {
+        Flow::Return(val) => Ok(val),
+        _ => Err(ScriptError::new(ErrorKind::Expression, format!("{name}(): can't exit here"))),
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// Tracks whether the text that's been scanned so far ends in a string.
+#[derive(Default)]
+struct Quotes {
+    quote: Option<char>,
+    escaped: bool,
+}
+
+impl Quotes {
+    // Returns whether the next char is part of a string, including its quotes.
+    fn next(&mut self, c: char) -> bool {
+        match (self.quote, c) {
+            (Some(_), _) if self.escaped => self.escaped = false,
+            (Some(_), '\\') => self.escaped = true,
+            (Some(q), _) if q == c => self.quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => self.quote = Some(c),
+            (None, _) => return false,
+        }
+
+        true
+    }
+}
+
+// Scans an expression forward, for the brackets that the text at `pos` is in, and whether each
+// of them has had a `&&` or `||` in it.
+struct Scanner {
+    pos: usize,
+    quotes: Quotes,
+    conditions: Vec<bool>,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            pos: 0,
+            quotes: Quotes::default(),
+            conditions: vec![false],
+        }
+    }
+}
+
+impl Scanner {
+    fn scan(&mut self, text: &str) {
+        for c in text.chars() {
+            if self.quotes.next(c) {
+                continue;
+            }
+
+            match c {
+                '(' | '[' | '{' => self.conditions.push(false),
+                ')' | ']' | '}' if self.conditions.len() > 1 => {
+                    self.conditions.pop();
+                }
+                '&' | '|' => *self.conditions.last_mut().unwrap() = true,
+                _ => (),
+            }
+        }
+
+        self.pos += text.len();
+    }
+
+    fn is_conditional(&self) -> bool {
+        self.conditions.contains(&true)
+    }
+}
+
+fn closing_paren(text: &str, open: usize) -> Option<usize> {
+    let mut quotes = Quotes::default();
+    let mut depth = 0;
+
+    for (i, c) in text[open..].char_indices().map(|(i, c)| (open + i, c)) {
+        match c {
+            _ if quotes.next(c) => (),
+            '(' => depth += 1,
+            ')' if depth == 1 => return Some(i),
+            ')' => depth -= 1,
+            _ => (),
+        }
+    }
+
+    None
+}
+
+// Splits arguments on commas that aren't nested in brackets or quotes.
+fn split_args(text: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut quotes = Quotes::default();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if quotes.next(c) => (),
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    match text[start..].trim() {
+        "" if args.is_empty() => args,
+        arg => [args, vec![arg]].concat(),
+    }
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const PROCS: &str = "[
+        {def: double, params: [x], do: [return: '${x * 2}']},
+        {def: add, params: [a, b], do: [return: '${a + b}']},
+        {def: hello, do: [echo: hello]},
+        {def: quit, do: [exit: 1]}
+    ]";
+
+    #[test]
+    fn resolve() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script.run_str(PROCS).unwrap();
+        script.binding.set_var("x", Yaml::Integer(3));
+
+        for e in [
+            ("${double(x) + 1}", Yaml::Integer(7)),
+            ("${add(double(1), add(x, 1))}", Yaml::Integer(6)),
+            ("${double(2) == 4}", Yaml::Boolean(true)),
+            ("${len('double(x)')}", Yaml::Integer(9)),
+            (r"${len('\'double(x)')}", Yaml::Integer(10)),
+            ("x: ${double(x)}", Yaml::from_str("x: 6")),
+            ("${max(x, 1)}", Yaml::Integer(3)),
+        ] {
+            assert_eq!(e.1, script.eval_to_yaml(&Yaml::from_str(e.0)).unwrap(), "{e:?}");
+        }
+
+        assert!(script.binding.calls.is_empty());
+    }
+
+    #[test]
+    fn resolve_side_effects() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script.run_str(PROCS).unwrap();
+
+        script.eval_to_yaml(&Yaml::from_str("${hello()}")).unwrap();
+        assert_eq!("hello", script.writer.log[0]);
+    }
+
+    #[test]
+    fn resolve_condition() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script.run_str(PROCS).unwrap();
+
+        script.run_str("[a: 1, {while: '${double(a) < 10}', do: [a: '${a + 1}']}]").unwrap();
+        assert_eq!(5, script.binding.var("a").as_i64().unwrap());
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let mut script = Script::new(String::new(), None);
+        script.run_str(PROCS).unwrap();
+
+        let err = script.eval_to_yaml(&Yaml::from_str("${double(1, 2)}")).unwrap_err();
+        assert_eq!(ErrorKind::Expression, err.kind);
+        assert_eq!("double() takes 1 argument(s), found 2", err.to_string());
+
+        let err = script.eval_to_yaml(&Yaml::from_str("${quit()}")).unwrap_err();
+        assert_eq!("quit(): can't exit here", err.to_string());
+    }
+
+    #[test]
+    fn resolve_conditional() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script.run_str(PROCS).unwrap();
+        script.binding.set_var("x", Yaml::Integer(3));
+
+        for e in [
+            ("${double(x) > 1 && x > 1}", Yaml::Boolean(true)),
+            ("${(x > 1 && x < 5) == (double(x) > 1)}", Yaml::Boolean(true)),
+            ("${x > 1 && 'double(x)' != ''}", Yaml::Boolean(true)),
+        ] {
+            assert_eq!(e.1, script.eval_to_yaml(&Yaml::from_str(e.0)).unwrap(), "{e:?}");
+        }
+
+        for e in [
+            ("${x > 5 && hello()}", "hello"),
+            ("${x > 1 || (x > 0 && double(x) > 1)}", "double"),
+            ("${add(x, x > 1 || double(x))}", "double"),
+        ] {
+            let err = script.eval_to_yaml(&Yaml::from_str(e.0)).unwrap_err();
+            assert_eq!(ErrorKind::Expression, err.kind);
+            let msg = format!(": {}() can't be called after && or ||", e.1);
+            assert!(err.message.ends_with(&msg), "{e:?}");
+        }
+
+        assert!(script.writer.log.is_empty());
+    }
+
+    #[test]
+    fn split_args() {
+        assert_eq!(Vec::<&str>::new(), super::split_args(" "));
+        assert_eq!(
+            vec!["a", "f(b, c)", "'d,e'", "[1, 2]"],
+            super::split_args("a, f(b, c), 'd,e', [1, 2]")
+        );
+        assert_eq!(vec![r"'a\',b'", "c"], super::split_args(r"'a\',b', c"));
+    }
+}
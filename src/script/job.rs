@@ -0,0 +1,193 @@
+use super::process;
+use super::redirect::{self, Redirect};
+use super::{ErrorKind, Script, ScriptError, Writer};
+use std::{
+    io,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::mpsc,
+    thread::JoinHandle,
+    time::Duration,
+};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// Commands started by a spawn step, which run in the background until they're waited for. Output
+// that isn't redirected goes where the script's does.
+pub struct Job {
+    pub line: String,
+    children: Vec<Child>,
+    input: Option<JoinHandle<io::Result<()>>>,
+    out: Option<JoinHandle<Vec<u8>>>,
+    err: Option<JoinHandle<Vec<u8>>>,
+    // The variables that stdout and stderr are redirected to, if any.
+    pub vars: [Option<String>; 2],
+    // Once they've finished.
+    output: Option<Output>,
+}
+
+impl Job {
+    pub fn start(
+        commands: Vec<Command>,
+        line: String,
+        redirects: [&Option<Redirect>; 3],
+        input: Option<String>,
+    ) -> io::Result<Self> {
+        let [_, stdout, stderr] = redirects;
+        let mut children = process::spawn(commands, redirects, Stdio::inherit)?;
+        let input = process::feed(&mut children, input);
+        let last = children.last_mut().ok_or(io::ErrorKind::InvalidInput)?;
+        let out = process::read(last.stdout.take(), None, false, true);
+        let err = process::read(last.stderr.take(), None, true, true);
+
+        Ok(Self {
+            line,
+            children,
+            input,
+            out: Some(out),
+            err: Some(err),
+            vars: [redirect::var(stdout), redirect::var(stderr)],
+            output: None,
+        })
+    }
+
+    // The pid of the last command, whose output is the result.
+    pub fn pid(&self) -> u32 {
+        self.children.last().map_or(0, Child::id)
+    }
+
+    // Waits for the commands to finish, and returns the output of the last one. If they're still
+    // running after the timeout, they're killed.
+    pub fn wait(&mut self, timeout: Option<Duration>, writer: &mut Writer) -> io::Result<Output> {
+        if let Some(output) = &self.output {
+            return Ok(output.clone());
+        }
+
+        // Nothing is streamed, so there are no lines to write.
+        let (_, rx) = mpsc::channel();
+        let status = process::wait(&mut self.children, timeout, rx, writer)?;
+
+        if let Some(input) = self.input.take() {
+            _ = input.join();
+        }
+
+        let join = |handle: Option<JoinHandle<Vec<u8>>>| {
+            handle.and_then(|h| h.join().ok()).unwrap_or_default()
+        };
+        let output = Output {
+            status,
+            stdout: join(self.out.take()),
+            stderr: join(self.err.take()),
+        };
+        self.output = Some(output.clone());
+
+        Ok(output)
+    }
+
+    // The status of the last command, if they've all finished.
+    pub fn status(&mut self) -> io::Result<Option<ExitStatus>> {
+        if let Some(output) = &self.output {
+            return Ok(Some(output.status));
+        }
+
+        if !process::finished(&mut self.children)? {
+            return Ok(None);
+        }
+
+        self.children.last_mut().ok_or(io::ErrorKind::InvalidInput)?.try_wait()
+    }
+
+    pub fn kill(&mut self) {
+        if self.output.is_none() {
+            process::kill(&mut self.children);
+        }
+    }
+}
+
+// Commands that are still running when the script ends are killed.
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// The id of the job that a handle refers to. A handle is what a spawn step binds, or its id.
+pub fn id(s: &mut Script, expr: &Yaml, key: &str) -> Result<usize, ScriptError> {
+    let id = match s.eval_to_yaml(expr)? {
+        Yaml::Hash(hash) => hash.get(&Yaml::from_str("id")).and_then(Yaml::as_i64),
+        yaml => yaml.as_i64(),
+    };
+    let id = id.ok_or_else(|| ScriptError::type_mismatch("process handle", key))?;
+
+    match usize::try_from(id) {
+        Ok(i) if i < s.jobs.len() => Ok(i),
+        _ => Err(ScriptError::new(ErrorKind::Argument, format!("no process with id {id}"))),
+    }
+}
+
+// The handle that a spawn step binds.
+pub fn handle(id: usize, job: &Job) -> Yaml {
+    let mut hash = Hash::new();
+    hash.insert(Yaml::from_str("id"), Yaml::Integer(id as i64));
+    hash.insert(Yaml::from_str("pid"), Yaml::Integer(job.pid().into()));
+    hash.insert(Yaml::from_str("command"), Yaml::String(job.line.clone()));
+
+    Yaml::Hash(hash)
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(line: &str, stdout: Option<Redirect>) -> Job {
+        let words = super::super::words::split(line).unwrap();
+        let mut command = Command::new(&words[0]);
+        command.args(&words[1..]);
+        Job::start(vec![command], line.into(), [&None, &stdout, &None], None).unwrap()
+    }
+
+    #[test]
+    fn wait() {
+        let mut writer = Writer::new(Some(Vec::new()));
+        let mut job = start("echo 1", Some(Redirect::Var("a".into())));
+
+        let output = job.wait(None, &mut writer).unwrap();
+        assert!(output.status.success());
+        assert_eq!(b"1\n", &output.stdout[..]);
+        assert_eq!([Some("a".into()), None], job.vars);
+
+        // The output is kept.
+        assert_eq!(output, job.wait(None, &mut writer).unwrap());
+    }
+
+    #[test]
+    fn status_kill() {
+        let mut writer = Writer::new(Some(Vec::new()));
+        let mut job = start("sleep 5", None);
+        assert!(job.pid() > 0);
+        assert_eq!(None, job.status().unwrap());
+
+        job.kill();
+        assert!(job.status().unwrap().is_some_and(|s| !s.success()));
+        assert_eq!(None, job.wait(None, &mut writer).unwrap().status.code());
+    }
+
+    #[test]
+    fn id() {
+        let mut script = Script::new(String::new(), None);
+        script.jobs.push(start("true", None));
+        script.binding.set_var("h", handle(0, &script.jobs[0]));
+
+        assert_eq!(0, super::id(&mut script, &Yaml::from_str("${h}"), "wait").unwrap());
+        assert_eq!(0, super::id(&mut script, &Yaml::Integer(0), "wait").unwrap());
+
+        let err = super::id(&mut script, &Yaml::Integer(1), "wait").unwrap_err();
+        assert_eq!(ErrorKind::Argument, err.kind);
+        assert_eq!("no process with id 1", err.to_string());
+
+        let err = super::id(&mut script, &Yaml::from_str("foo"), "wait").unwrap_err();
+        assert_eq!("expected process handle for 'wait'", err.to_string());
+    }
+}
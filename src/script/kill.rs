@@ -0,0 +1,32 @@
+use super::{job, Script, ScriptError};
+use yaml_rust2::Yaml;
+
+// - kill: <handle>
+//
+// Kills a spawned command, unless it's already finished. Waiting for it afterwards gives a null
+// code.
+pub fn run(s: &mut Script, expr: &Yaml) -> Result<(), ScriptError> {
+    let id = job::id(s, expr, "kill")?;
+    s.jobs[id].kill();
+    Ok(())
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::{spawn, wait, Binding};
+    use super::*;
+    use yaml_rust2::yaml::Hash;
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        spawn::run(&mut script, &Yaml::from_str("sleep 5"), &Hash::new()).unwrap();
+
+        super::run(&mut script, &Yaml::from_str("${_}")).unwrap();
+        wait::run(&mut script, &Yaml::Integer(0), &Binding::hash_from_str("as: a")).unwrap();
+        assert_eq!(Yaml::Null, script.binding.var("a")["code"]);
+        assert!(!script.binding.var("a")["ok"].as_bool().unwrap());
+    }
+}
@@ -0,0 +1,251 @@
+use super::error::{Location, ScriptError};
+use super::step;
+use std::{collections::HashMap, sync::Arc};
+use yaml_rust2::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::Marker,
+    yaml::Hash,
+    Yaml, YamlLoader,
+};
+
+// Source locations of the steps of loaded documents, by the address of each step's mapping, so
+// that identical steps have their own, and the steps themselves are left as they are. Marks are
+// only valid while the documents they're for are kept, unchanged, so they're dropped with them.
+pub type Marks = HashMap<usize, Location>;
+
+// The documents of a file, with where each of them starts.
+pub type Docs = Vec<(Yaml, Option<Location>)>;
+
+// Loads the documents of a file, and the locations of their steps.
+pub fn load(path: &str, text: &str) -> Result<(Docs, Marks), ScriptError> {
+    let docs = YamlLoader::load_from_str(text).map_err(|e| ScriptError::parse(path, e))?;
+    let mut recv = Receiver::default();
+    Parser::new_from_str(text).load(&mut recv, true).map_err(|e| ScriptError::parse(path, e))?;
+
+    // Mappings are started in the same (pre-)order that they appear in the documents. The steps
+    // are in lists, or in the mappings of named documents, whose contents don't move with them.
+    let mut iter = recv.marks.iter();
+    let mut walker = Walker {
+        path,
+        marks: Marks::new(),
+    };
+    let locations: Vec<Option<Location>> = docs
+        .iter()
+        .map(|doc| match doc {
+            Yaml::Hash(hash) => walker.doc(hash, &mut iter),
+            yaml => {
+                walker.steps(yaml, &mut iter);
+                None
+            }
+        })
+        .collect();
+
+    Ok((docs.into_iter().zip(locations).collect(), walker.marks))
+}
+
+// The location of a step, in the innermost marks that have it.
+pub fn location<'a>(marks: &'a [Arc<Marks>], step: &Hash) -> Option<&'a Location> {
+    marks.iter().rev().find_map(|m| m.get(&key(step)))
+}
+
+// The locations of the steps in a copy of a step (e.g. a def), from those of the original.
+pub fn copy(marks: &[Arc<Marks>], from: &Hash, to: &Hash) -> Marks {
+    let mut copied = Marks::new();
+
+    for (from, to) in from.values().zip(to.values()) {
+        copy_into(marks, from, to, &mut copied);
+    }
+
+    copied
+}
+
+fn copy_into(marks: &[Arc<Marks>], from: &Yaml, to: &Yaml, copied: &mut Marks) {
+    match (from, to) {
+        (Yaml::Array(from), Yaml::Array(to)) => {
+            from.iter().zip(to).for_each(|(f, t)| copy_into(marks, f, t, copied))
+        }
+        (Yaml::Hash(from), Yaml::Hash(to)) => {
+            if let Some(location) = location(marks, from) {
+                copied.insert(key(to), location.clone());
+            }
+
+            from.values().zip(to.values()).for_each(|(f, t)| copy_into(marks, f, t, copied))
+        }
+        _ => (),
+    }
+}
+
+fn key(step: &Hash) -> usize {
+    step as *const Hash as usize
+}
+
+struct Walker<'a> {
+    path: &'a str,
+    marks: Marks,
+}
+
+impl Walker<'_> {
+    // A mapping document is a header, or a named document with steps (see `doc`).
+    fn doc<'a, I>(&mut self, hash: &Hash, iter: &mut I) -> Option<Location>
+    where
+        I: Iterator<Item = &'a Marker>,
+    {
+        let location = iter.next().map(|mark| Location::new(self.path, mark));
+
+        for (key, val) in hash {
+            skip(key, iter);
+
+            match key.as_str() {
+                Some("do") => self.steps(val, iter),
+                _ => skip(val, iter),
+            }
+        }
+
+        location
+    }
+
+    // Steps are mappings in a list, or in a list of lists (e.g. the branches of a parallel step).
+    fn steps<'a, I>(&mut self, yaml: &Yaml, iter: &mut I)
+    where
+        I: Iterator<Item = &'a Marker>,
+    {
+        match yaml {
+            Yaml::Array(list) => list.iter().for_each(|e| self.steps(e, iter)),
+            Yaml::Hash(hash) => self.step(hash, iter),
+            _ => (),
+        }
+    }
+
+    fn step<'a, I>(&mut self, hash: &Hash, iter: &mut I)
+    where
+        I: Iterator<Item = &'a Marker>,
+    {
+        let Some(mark) = iter.next() else {
+            return;
+        };
+
+        let name = hash.front().and_then(|(key, _)| key.as_str()).unwrap_or_default();
+        let nested = step::nested(name);
+
+        for (key, val) in hash {
+            skip(key, iter);
+
+            match key.as_str() {
+                Some(key) if nested.contains(&key) && val.is_array() => self.steps(val, iter),
+                _ => skip(val, iter),
+            }
+        }
+
+        self.marks.insert(key(hash), Location::new(self.path, mark));
+    }
+}
+
+// Skips the marks of the mappings in a value that has no steps.
+fn skip<'a, I>(yaml: &Yaml, iter: &mut I)
+where
+    I: Iterator<Item = &'a Marker>,
+{
+    match yaml {
+        Yaml::Array(list) => list.iter().for_each(|e| skip(e, iter)),
+        Yaml::Hash(hash) => {
+            iter.next();
+
+            for (key, val) in hash {
+                skip(key, iter);
+                skip(val, iter);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[derive(Default)]
+struct Receiver {
+    marks: Vec<Marker>,
+    in_mapping: bool,
+}
+
+impl MarkedEventReceiver for Receiver {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        // A block mapping is marked at its first ':', so prefer the start of its first key.
+        match ev {
+            Event::MappingStart(..) => self.marks.push(mark),
+            Event::Scalar(..) if self.in_mapping => *self.marks.last_mut().unwrap() = mark,
+            _ => (),
+        }
+
+        self.in_mapping = matches!(ev, Event::MappingStart(..));
+    }
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marked(text: &str) -> (Docs, Vec<Arc<Marks>>) {
+        let (docs, marks) = super::load("foo.ys", text).unwrap();
+        (docs, vec![Arc::new(marks)])
+    }
+
+    #[test]
+    fn load() {
+        let (docs, marks) = marked("- echo: foo\n- if: true\n  then:\n    - echo: bar\n");
+        assert_eq!(1, docs.len());
+        assert_eq!(None, docs[0].1);
+
+        let step = docs[0].0[1]["then"][0].as_hash().unwrap();
+        assert_eq!("foo.ys:4:7", location(&marks, step).unwrap().to_string());
+
+        // The steps are as they were written.
+        assert_eq!(1, step.len());
+        assert_eq!(2, docs[0].0[1].as_hash().unwrap().len());
+    }
+
+    #[test]
+    fn load_identical() {
+        let (docs, marks) = marked("- echo: foo\n- echo: bar\n- echo: foo\n");
+        let locations: Vec<String> = (0..3)
+            .map(|i| location(&marks, docs[0].0[i].as_hash().unwrap()).unwrap().to_string())
+            .collect();
+        assert_eq!(vec!["foo.ys:1:3", "foo.ys:2:3", "foo.ys:3:3"], locations);
+
+        // Only the steps themselves are marked, not those that are equal to them.
+        let copy = docs[0].0[0].as_hash().unwrap().clone();
+        assert_eq!(None, location(&marks, &copy));
+    }
+
+    #[test]
+    fn load_data() {
+        let (docs, marks) =
+            marked("name: a\nhelp: {x: 1}\ndo:\n  - x: [{a: 1}]\n  - parallel: [[echo: a]]\n");
+        let doc = &docs[0].0;
+        assert_eq!("foo.ys:1:1", docs[0].1.as_ref().unwrap().to_string());
+
+        // Only steps are marked.
+        assert_eq!(None, location(&marks, doc.as_hash().unwrap()));
+        assert_eq!(None, location(&marks, doc["do"][0]["x"][0].as_hash().unwrap()));
+
+        let step = doc["do"][1]["parallel"][0][0].as_hash().unwrap();
+        assert_eq!("foo.ys:5:17", location(&marks, step).unwrap().to_string());
+        assert_eq!(3, marks[0].len());
+    }
+
+    #[test]
+    fn copy() {
+        let (docs, marks) = marked("- def: f\n  do:\n    - echo: foo\n");
+        let step = docs[0].0[0].as_hash().unwrap();
+        let def = step.clone();
+
+        let copied = vec![Arc::new(super::copy(&marks, step, &def))];
+        let step = def[&Yaml::from_str("do")][0].as_hash().unwrap();
+        assert_eq!("foo.ys:3:7", location(&copied, step).unwrap().to_string());
+    }
+
+    #[test]
+    fn load_error() {
+        let err = super::load("foo.ys", "- echo: [foo").unwrap_err();
+        assert_eq!("foo.ys", err.location.unwrap().path);
+    }
+}
@@ -0,0 +1,43 @@
+use super::{Script, ScriptError};
+use yaml_rust2::Yaml;
+
+// - local:
+//     <name>: <expression>
+//     ...
+pub fn run(s: &mut Script, vars: &Yaml) -> Result<(), ScriptError> {
+    let hash = vars.as_hash().ok_or_else(|| ScriptError::type_mismatch("mapping", "local"))?;
+
+    for (name, val) in s.eval_to_frame(hash, "local")? {
+        s.binding.set_local(name, val);
+    }
+
+    Ok(())
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::binding::{Binding, Frame};
+    use super::*;
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("local: {a: 1, b: 2}");
+        script.binding.push_frame(Frame::new());
+
+        super::run(&mut script, &hash[&Yaml::from_str("local")]).unwrap();
+        assert_eq!(1, script.binding.var("a").as_i64().unwrap());
+        assert_eq!(2, script.binding.var("b").as_i64().unwrap());
+        assert!(script.binding.vars.is_empty());
+    }
+
+    #[test]
+    fn run_not_mapping() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Yaml::from_str("a")).unwrap_err();
+        assert_eq!("expected mapping for 'local'", err.to_string());
+    }
+}
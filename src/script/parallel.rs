@@ -0,0 +1,316 @@
+use super::{Binding, ErrorKind, Flow, Script, ScriptError};
+use std::{slice, sync::Mutex, thread};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - parallel: <list of steps | lists of steps>
+//   [max: <number>]
+//   [share: <list of names>]
+//
+// Runs each step, or list of steps, on a thread of its own, at most `max` at once (default all of
+// them), and waits for them all. Each branch runs with a copy of the variables, so what it sets
+// isn't seen by the others, or afterwards, unless it's shared. It's an error if any of them fail.
+pub fn run(s: &mut Script, branches: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
+    let branches =
+        branches.as_vec().ok_or_else(|| ScriptError::type_mismatch("list", "parallel"))?;
+    let branches = branches
+        .iter()
+        .map(|b| match b {
+            Yaml::Array(steps) => Ok(steps.as_slice()),
+            Yaml::Hash(_) => Ok(slice::from_ref(b)),
+            _ => Err(ScriptError::type_mismatch("step or list of steps", "parallel")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let max = max(s, step, "max")?;
+    let share = share(step)?;
+
+    let results = run_branches(s, branches.len(), max, |s, i| s.run_steps(branches[i]), |_| false);
+    join(s, results, &share)
+}
+
+// The number of branches to run at once, if it's limited.
+pub fn max(s: &mut Script, step: &Hash, key: &str) -> Result<Option<usize>, ScriptError> {
+    match step.get(&Yaml::from_str(key)).map(|m| s.eval_to_i32(m)).transpose()? {
+        Some(max) if max < 1 => Err(ScriptError::type_mismatch("positive number", key)),
+        max => Ok(max.map(|m| m as usize)),
+    }
+}
+
+pub fn share(step: &Hash) -> Result<Vec<String>, ScriptError> {
+    Binding::entry_to_list_or_empty(step, "share")?
+        .iter()
+        .map(|n| Binding::yaml_to_name(n, "share").map(String::from))
+        .collect()
+}
+
+//-----------------------------------------------------------------------------
+
+type Results = Vec<(Script, Result<Flow, ScriptError>)>;
+
+// Runs `f` for each branch, by index, on up to `max` threads at once, each with its own copy of the
+// script, until a result is one to `stop` at, after which no more are started. Returns the copies,
+// with the results, in order, for those that were run.
+pub fn run_branches<F, S>(s: &Script, count: usize, max: Option<usize>, f: F, stop: S) -> Results
+where
+    F: Fn(&mut Script, usize) -> Result<Flow, ScriptError> + Sync,
+    S: Fn(&Result<Flow, ScriptError>) -> bool + Sync,
+{
+    // The index of the next branch to run, which is past the end once they should stop.
+    let next = Mutex::new(0);
+    let results = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..max.unwrap_or(count).min(count) {
+            scope.spawn(|| loop {
+                let i = {
+                    let mut next = next.lock().unwrap();
+                    *next += 1;
+                    *next - 1
+                };
+
+                if i >= count {
+                    break;
+                }
+
+                let mut branch = branch(s);
+                let res = f(&mut branch, i);
+
+                if stop(&res) {
+                    *next.lock().unwrap() = count;
+                }
+
+                results.lock().unwrap().push((i, branch, res));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|r| r.0);
+    results.into_iter().map(|(_, branch, res)| (branch, res)).collect()
+}
+
+// A copy of the script to run a branch with. Commands it spawns are its own, and are killed when
+// it ends, unless they've been waited for.
+fn branch(s: &Script) -> Script {
+    Script {
+        path: s.path.clone(),
+        binding: s.binding.clone(),
+        writer: s.writer.branch(),
+        marks: s.marks.clone(),
+        jobs: Vec::new(),
+        args: s.args.clone(),
+        files: s.files.clone(),
+        task: None,
+    }
+}
+
+// Logs what the branches logged, and sets the shared variables that they set, in order. Then if
+// any failed, fails with all of their errors, or else exits if any exited.
+pub fn join(s: &mut Script, results: Results, share: &[String]) -> Result<Flow, ScriptError> {
+    let before: Vec<Yaml> = share.iter().map(|n| s.binding.var(n)).collect();
+    let count = results.len();
+    let mut errors = Vec::new();
+    let mut exit = None;
+
+    for (mut branch, res) in results {
+        s.writer.log.append(&mut branch.writer.log);
+
+        for (name, before) in share.iter().zip(&before) {
+            let val = branch.binding.var(name);
+
+            if val != *before {
+                s.binding.set_var(name, val);
+            }
+        }
+
+        match res {
+            Ok(Flow::Next) => (),
+            Ok(Flow::Exit(code)) => exit = exit.or(Some(code)),
+            Ok(flow) => errors.push(flow_error(&flow)),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.len() > 1 {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        let message =
+            format!("{} of {count} branches failed: {}", errors.len(), messages.join("; "));
+        let mut err = ScriptError::new(errors[0].kind, message);
+        err.code = errors[0].code;
+        return Err(err);
+    }
+
+    match (errors.pop(), exit) {
+        (Some(err), _) => Err(err),
+        (None, Some(code)) => Ok(Flow::Exit(code)),
+        (None, None) => Ok(Flow::Next),
+    }
+}
+
+// Breaks, continues and returns can't reach a loop or call outside of the thread of a branch.
+fn flow_error(flow: &Flow) -> ScriptError {
+    let name = match flow {
+        Flow::Break { .. } => "break",
+        Flow::Continue { .. } => "continue",
+        Flow::Return(_) => "return",
+        _ => return flow.error(),
+    };
+    let msg = match flow {
+        Flow::Break {
+            label: Some(label), ..
+        }
+        | Flow::Continue { label: Some(label) } => {
+            format!("'{name}' to '{label}' can't leave a parallel branch")
+        }
+        _ => format!("'{name}' can't leave a parallel branch"),
+    };
+
+    ScriptError::new(ErrorKind::Flow, msg)
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn run(text: &str) -> (Script, Result<Flow, ScriptError>) {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let res = script.run_str(text);
+        (script, res)
+    }
+
+    #[test]
+    fn run_branches() {
+        let text = "
+- parallel:
+    - echo: a
+    - [{echo: b}, {echo: c}]
+- echo: d
+";
+
+        let (script, res) = run(text);
+        assert_eq!(Flow::Next, res.unwrap());
+        assert_eq!(vec!["a", "b", "c", "d"], script.writer.log);
+    }
+
+    #[test]
+    fn run_concurrently() {
+        let text = "
+- parallel:
+    - exec: sleep 0.3
+    - exec: sleep 0.3
+    - exec: sleep 0.3
+";
+
+        let start = Instant::now();
+        run(text).1.unwrap();
+        assert!(start.elapsed().as_secs_f64() < 0.8);
+    }
+
+    #[test]
+    fn run_max() {
+        let text = "
+- parallel:
+    - exec: sleep 0.2
+    - exec: sleep 0.2
+  max: 1
+";
+
+        let start = Instant::now();
+        run(text).1.unwrap();
+        assert!(start.elapsed().as_secs_f64() >= 0.4);
+    }
+
+    #[test]
+    fn run_share() {
+        let text = "
+- x: 0
+- y: 0
+- parallel:
+    - x: 1
+    - y: 2
+    - [{z: 3}, {echo: '${x}'}]
+  share: [x, z]
+";
+
+        let (script, res) = run(text);
+        res.unwrap();
+        assert_eq!(1, script.binding.var("x").as_i64().unwrap());
+        assert_eq!(0, script.binding.var("y").as_i64().unwrap());
+        assert_eq!(3, script.binding.var("z").as_i64().unwrap());
+        // Each branch has its own copy.
+        assert_eq!(vec!["0"], script.writer.log);
+    }
+
+    #[test]
+    fn run_failed() {
+        let text = "
+- parallel:
+    - echo: a
+    - exec: sh -c 'exit 3'
+      check: true
+";
+
+        let (script, res) = run(text);
+        let err = res.unwrap_err();
+        assert_eq!(Some(3), err.code);
+        assert_eq!(vec!["a"], script.writer.log);
+
+        let text = "
+- parallel:
+    - exec: sh -c 'exit 3'
+      check: true
+    - break: true
+    - echo: c
+";
+
+        let err = run(text).1.unwrap_err();
+        assert_eq!(ErrorKind::Exec, err.kind);
+        assert_eq!(Some(3), err.code);
+        assert!(err.message.starts_with("2 of 3 branches failed: "), "{err}");
+        assert!(err.message.ends_with("; 'break' can't leave a parallel branch"), "{err}");
+    }
+
+    #[test]
+    fn run_exit() {
+        let (script, res) = run("[{parallel: [{exit: 2}, {echo: a}]}, {echo: b}]");
+        assert_eq!(Flow::Exit(2), res.unwrap());
+        assert_eq!(vec!["a"], script.writer.log);
+    }
+
+    #[test]
+    fn run_return() {
+        let text = "
+- def: foo
+  do:
+    - parallel:
+        - return: 1
+        - echo: a
+    - echo: b
+- call: foo
+";
+
+        let (script, res) = run(text);
+        let err = res.unwrap_err();
+        assert_eq!(ErrorKind::Flow, err.kind);
+        assert_eq!("'return' can't leave a parallel branch", err.message);
+        assert_eq!(vec!["a"], script.writer.log);
+    }
+
+    #[test]
+    fn run_errors() {
+        for e in [
+            ("{parallel: 1}", "expected list for 'parallel'"),
+            ("{parallel: [1]}", "expected step or list of steps for 'parallel'"),
+            ("{parallel: [], max: 0}", "expected positive number for 'max'"),
+            ("{parallel: [], share: [[]]}", "expected name for 'share'"),
+        ] {
+            let mut script = Script::new(String::new(), None);
+            let hash = Binding::hash_from_str(e.0);
+            let branches = &hash[&Yaml::from_str("parallel")];
+            let err = super::run(&mut script, branches, &hash).unwrap_err();
+            assert_eq!(e.1, err.to_string(), "{e:?}");
+        }
+    }
+}
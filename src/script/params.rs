@@ -0,0 +1,262 @@
+use super::{Binding, ErrorKind, Frame, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+const TYPES: [&str; 8] = [
+    "any", "boolean", "integer", "float", "number", "string", "list", "map",
+];
+
+#[derive(Debug, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub kind: String,
+    pub default: Option<Yaml>,
+    pub required: bool,
+    pub help: Option<String>,
+}
+
+// params:
+//   - <name>
+//   - name: <name>
+//     [type: any | boolean | integer | float | number | string | list | map]
+//     [default: <expression>]
+//     [required: <boolean>]
+//     [help: <string>]
+//
+// Returns None if the params aren't declared, in which case any arguments are accepted.
+pub fn parse(def: &Hash) -> Result<Option<Vec<Param>>, ScriptError> {
+    if !def.contains_key(&Yaml::from_str("params")) {
+        return Ok(None);
+    }
+
+    let list = Binding::entry_to_list(def, "params")?;
+    list.iter().map(parse_param).collect::<Result<_, _>>().map(Some)
+}
+
+fn parse_param(yaml: &Yaml) -> Result<Param, ScriptError> {
+    let hash = match yaml {
+        Yaml::String(name) => return Ok(Param::new(name)),
+        Yaml::Hash(hash) => hash,
+        _ => return Err(ScriptError::type_mismatch("name or mapping", "params")),
+    };
+
+    let name =
+        Binding::entry_to_name(hash, "name")?.ok_or_else(|| ScriptError::missing_key("name"))?;
+    let kind = Binding::entry_to_name(hash, "type")?.unwrap_or("any");
+    let default = hash.get(&Yaml::from_str("default")).cloned();
+    let required = match hash.get(&Yaml::from_str("required")) {
+        Some(Yaml::Boolean(b)) => *b,
+        Some(_) => return Err(ScriptError::type_mismatch("boolean", "required")),
+        None => default.is_none(),
+    };
+    let help = match hash.get(&Yaml::from_str("help")) {
+        Some(Yaml::String(help)) => Some(help.clone()),
+        Some(_) => return Err(ScriptError::type_mismatch("string", "help")),
+        None => None,
+    };
+
+    if !TYPES.contains(&kind) {
+        return Err(ScriptError::new(ErrorKind::TypeMismatch, format!("unknown type '{kind}'")));
+    }
+
+    Ok(Param {
+        kind: kind.into(),
+        default,
+        required,
+        help,
+        ..Param::new(name)
+    })
+}
+
+impl Param {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            kind: "any".into(),
+            default: None,
+            required: true,
+            help: None,
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// Checks the arguments of a call to a proc against its params, and fills in defaults.
+pub fn bind(
+    s: &mut Script,
+    proc: &str,
+    params: &[Param],
+    mut args: Frame,
+) -> Result<Frame, ScriptError> {
+    if let Some(name) = args.keys().find(|k| !params.iter().any(|p| &p.name == *k)) {
+        return Err(argument_error(proc, format!("unknown argument '{name}'")));
+    }
+
+    for param in params {
+        let val = match (args.remove(&param.name), &param.default) {
+            (Some(val), _) => val,
+            (None, Some(default)) => s.eval_to_yaml(default)?,
+            (None, None) if param.required => {
+                return Err(argument_error(proc, format!("missing argument '{}'", param.name)));
+            }
+            // An optional param without a value is null, whatever its type.
+            (None, None) => {
+                args.insert(param.name.clone(), Yaml::Null);
+                continue;
+            }
+        };
+
+        if !is_type(&val, &param.kind) {
+            let (kind, name, found) = (&param.kind, &param.name, type_name(&val));
+            let msg = format!("{proc}: expected {kind} for '{name}', found {found}");
+            return Err(ScriptError::new(ErrorKind::TypeMismatch, msg));
+        }
+
+        args.insert(param.name.clone(), val);
+    }
+
+    Ok(args)
+}
+
+fn is_type(val: &Yaml, kind: &str) -> bool {
+    match (kind, type_name(val)) {
+        ("any", _) => true,
+        ("float" | "number", found) => matches!(found, "integer" | "float"),
+        (kind, found) => kind == found,
+    }
+}
+
+fn type_name(val: &Yaml) -> &str {
+    match val {
+        Yaml::Boolean(_) => "boolean",
+        Yaml::Integer(_) => "integer",
+        Yaml::Real(_) => "float",
+        Yaml::String(_) => "string",
+        Yaml::Array(_) => "list",
+        Yaml::Hash(_) => "map",
+        _ => "null",
+    }
+}
+
+fn argument_error(proc: &str, msg: String) -> ScriptError {
+    ScriptError::new(ErrorKind::Argument, format!("{proc}: {msg}"))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(text: &str) -> Vec<Param> {
+        super::parse(&Binding::hash_from_str(text)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let params = parse_str("params: [a, {name: b, type: integer, default: 1}, {name: c}]");
+
+        assert_eq!(Param::new("a"), params[0]);
+        assert_eq!("integer", params[1].kind);
+        assert_eq!(Some(Yaml::Integer(1)), params[1].default);
+        assert!(!params[1].required);
+        assert_eq!(Param::new("c"), params[2]);
+    }
+
+    #[test]
+    fn parse_none() {
+        assert_eq!(None, super::parse(&Hash::new()).unwrap());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for e in [
+            ("params: a", "expected list for 'params'"),
+            ("params: [1]", "expected name or mapping for 'params'"),
+            ("params: [{type: integer}]", "expected 'name'"),
+            ("params: [{name: a, type: foo}]", "unknown type 'foo'"),
+            ("params: [{name: a, required: 1}]", "expected boolean for 'required'"),
+            ("params: [{name: a, help: [1]}]", "expected string for 'help'"),
+        ] {
+            let err = super::parse(&Binding::hash_from_str(e.0)).unwrap_err();
+            assert_eq!(e.1, err.to_string(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn bind() {
+        let mut script = Script::new(String::new(), None);
+        let params =
+            parse_str("params: [a, {name: b, default: '${1 + 1}'}, {name: c, required: false}]");
+        let args = Frame::from([("a".into(), Yaml::Integer(1))]);
+
+        let frame = super::bind(&mut script, "foo", &params, args).unwrap();
+        assert_eq!(Some(&Yaml::Integer(1)), frame.get("a"));
+        assert_eq!(Some(&Yaml::Integer(2)), frame.get("b"));
+        assert_eq!(Some(&Yaml::Null), frame.get("c"));
+    }
+
+    #[test]
+    fn bind_missing() {
+        let mut script = Script::new(String::new(), None);
+        let params = parse_str("params: [a]");
+
+        let err = super::bind(&mut script, "foo", &params, Frame::new()).unwrap_err();
+        assert_eq!(ErrorKind::Argument, err.kind);
+        assert_eq!("foo: missing argument 'a'", err.to_string());
+    }
+
+    #[test]
+    fn bind_unknown() {
+        let mut script = Script::new(String::new(), None);
+        let args = Frame::from([("b".into(), Yaml::Integer(1))]);
+
+        let err = super::bind(&mut script, "foo", &parse_str("params: []"), args).unwrap_err();
+        assert_eq!(ErrorKind::Argument, err.kind);
+        assert_eq!("foo: unknown argument 'b'", err.to_string());
+    }
+
+    #[test]
+    fn bind_type() {
+        let mut script = Script::new(String::new(), None);
+        let params = parse_str("params: [{name: a, type: integer}]");
+        let args = Frame::from([("a".into(), Yaml::from_str("x"))]);
+
+        let err = super::bind(&mut script, "foo", &params, args).unwrap_err();
+        assert_eq!(ErrorKind::TypeMismatch, err.kind);
+        assert_eq!("foo: expected integer for 'a', found string", err.to_string());
+    }
+
+    #[test]
+    fn bind_null() {
+        let mut script = Script::new(String::new(), None);
+        let params =
+            parse_str("params: [{name: a, type: map}, {name: b, type: list, required: false}]");
+
+        let args = Frame::from([("a".into(), Yaml::Null)]);
+        let err = super::bind(&mut script, "foo", &params, args).unwrap_err();
+        assert_eq!("foo: expected map for 'a', found null", err.to_string());
+
+        let args = Frame::from([("a".into(), Yaml::Hash(Hash::new()))]);
+        let frame = super::bind(&mut script, "foo", &params, args).unwrap();
+        assert_eq!(Some(&Yaml::Null), frame.get("b"));
+    }
+
+    #[test]
+    fn is_type() {
+        for e in [
+            (Yaml::Integer(1), "integer", true),
+            (Yaml::Integer(1), "float", true),
+            (Yaml::Integer(1), "number", true),
+            (Yaml::Real("1.5".into()), "number", true),
+            (Yaml::Real("1.5".into()), "integer", false),
+            (Yaml::from_str("x"), "string", true),
+            (Yaml::from_str("x"), "list", false),
+            (Yaml::Null, "map", false),
+            (Yaml::Null, "any", true),
+            (Yaml::Array(Vec::new()), "any", true),
+        ] {
+            assert_eq!(e.2, super::is_type(&e.0, e.1), "{e:?}");
+        }
+    }
+}
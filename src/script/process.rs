@@ -0,0 +1,173 @@
+use super::redirect::{self, Redirect};
+use super::Writer;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// A line of output, and whether it's from stderr.
+pub type Line = (String, bool);
+
+const TICK: Duration = Duration::from_millis(10);
+
+// Starts the commands with the stdout of each piped to the stdin of the next. Where the output of
+// the last one goes by default is up to the caller.
+pub fn spawn(
+    commands: Vec<Command>,
+    redirects: [&Option<Redirect>; 3],
+    default: fn() -> Stdio,
+) -> io::Result<Vec<Child>> {
+    let [stdin, stdout, stderr] = redirects;
+    let count = commands.len();
+    let mut children: Vec<Child> = Vec::new();
+    let mut pipe = Some(redirect::stdio(stdin, false, Stdio::null)?);
+    let mut out = Some(redirect::stdio(stdout, true, default)?);
+    let mut err = Some(redirect::stdio(stderr, true, default)?);
+
+    for (i, mut command) in commands.into_iter().enumerate() {
+        let last = i + 1 == count;
+        command.stdin(pipe.take().unwrap_or_else(Stdio::null));
+
+        if last {
+            command.stdout(out.take().unwrap_or_else(default));
+            command.stderr(err.take().unwrap_or_else(default));
+        } else {
+            command.stdout(Stdio::piped());
+        }
+
+        match command.spawn() {
+            Ok(mut child) if !last => {
+                pipe = child.stdout.take().map(Stdio::from);
+                children.push(child);
+            }
+            Ok(child) => children.push(child),
+            Err(e) => {
+                kill(&mut children);
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(children)
+}
+
+// Feeds input to the first command on another thread, so that a command can write more output
+// than a pipe holds before it's read all of its input.
+pub fn feed(children: &mut [Child], input: Option<String>) -> Option<JoinHandle<io::Result<()>>> {
+    match (input, children.first_mut().and_then(|c| c.stdin.take())) {
+        (Some(input), Some(mut pipe)) => {
+            Some(thread::spawn(move || pipe.write_all(input.as_bytes())))
+        }
+        _ => None,
+    }
+}
+
+// Reads a pipe to the end on another thread, so that reading one pipe doesn't block the others.
+// If it's teed, each line is sent to be written as it comes, and only kept if asked to.
+pub fn read<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    tee: Option<Sender<Line>>,
+    err: bool,
+    keep: bool,
+) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+
+        match (pipe, tee) {
+            (Some(pipe), Some(tee)) => {
+                let mut reader = BufReader::new(pipe);
+                let mut line = Vec::new();
+
+                while reader.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+                    _ = tee.send((text(&line), err));
+
+                    if keep {
+                        buf.append(&mut line);
+                    } else {
+                        line.clear();
+                    }
+                }
+            }
+            (Some(mut pipe), None) => _ = pipe.read_to_end(&mut buf),
+            (None, _) => (),
+        }
+
+        buf
+    })
+}
+
+// Writes the lines of output that are sent as they come, then waits for all of the commands to
+// finish, and returns the status of the last one. If they're still running after the timeout,
+// they're killed.
+pub fn wait(
+    children: &mut [Child],
+    timeout: Option<Duration>,
+    lines: Receiver<Line>,
+    writer: &mut Writer,
+) -> io::Result<ExitStatus> {
+    let start = Instant::now();
+
+    loop {
+        check_timeout(children, start, timeout)?;
+
+        match lines.recv_timeout(TICK) {
+            Ok((line, false)) => writer.write(line),
+            Ok((line, true)) => writer.write_err(line),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    while timeout.is_some() && !finished(children)? {
+        check_timeout(children, start, timeout)?;
+        thread::sleep(TICK);
+    }
+
+    let mut status = Err(io::ErrorKind::InvalidInput.into());
+
+    for child in children {
+        status = child.wait();
+    }
+
+    status
+}
+
+fn check_timeout(
+    children: &mut [Child],
+    start: Instant,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    match timeout {
+        Some(timeout) if start.elapsed() >= timeout => {
+            kill(children);
+            let msg = format!("timed out after {}s", timeout.as_secs_f64());
+            Err(io::Error::new(io::ErrorKind::TimedOut, msg))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn finished(children: &mut [Child]) -> io::Result<bool> {
+    for child in children {
+        if child.try_wait()?.is_none() {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+pub fn kill(children: &mut [Child]) {
+    for child in children {
+        _ = child.kill().and_then(|_| child.wait());
+    }
+}
+
+// Drops the trailing newline, if any.
+pub fn text(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    text.strip_suffix('\n').unwrap_or(&text).to_string()
+}
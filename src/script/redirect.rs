@@ -0,0 +1,132 @@
+use super::{Binding, Script, ScriptError};
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    process::Stdio,
+};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// Where a standard stream of a command comes from or goes to, other than the default (nothing for
+// stdin, and the result for stdout and stderr).
+#[derive(Debug, PartialEq)]
+pub enum Redirect {
+    File { path: String, append: bool },
+    Var(String),
+    Text(String),
+}
+
+impl Redirect {
+    // stdin: <expression> | {file: <path>} | {var: <name>}
+    // stdout | stderr: <path> | {file: <path>, [append: <boolean>]} | {var: <name>}
+    pub fn parse(s: &mut Script, step: &Hash, key: &str) -> Result<Option<Self>, ScriptError> {
+        let hash = match step.get(&Yaml::from_str(key)) {
+            Some(Yaml::Hash(hash)) => hash,
+            Some(yaml) if key == "stdin" => {
+                return Ok(Some(Redirect::Text(s.eval_to_string(yaml)?)))
+            }
+            Some(yaml) => {
+                let path = s.eval_to_string(yaml)?;
+                return Ok(Some(Redirect::File {
+                    path,
+                    append: false,
+                }));
+            }
+            None => return Ok(None),
+        };
+
+        if let Some(name) = Binding::entry_to_name(hash, "var")? {
+            return Ok(Some(Redirect::Var(name.into())));
+        }
+
+        let path =
+            hash.get(&Yaml::from_str("file")).ok_or_else(|| ScriptError::missing_key("file"))?;
+        let append = match hash.get(&Yaml::from_str("append")) {
+            Some(Yaml::Boolean(b)) => *b,
+            Some(_) => return Err(ScriptError::type_mismatch("boolean", "append")),
+            None => false,
+        };
+
+        Ok(Some(Redirect::File {
+            path: s.eval_to_string(path)?,
+            append,
+        }))
+    }
+}
+
+// The name of the variable that output is redirected to, if any.
+pub fn var(redirect: &Option<Redirect>) -> Option<String> {
+    match redirect {
+        Some(Redirect::Var(name)) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+// The stdio for a stream of a command: a file, a pipe to or from the script, or the default.
+pub fn stdio(
+    redirect: &Option<Redirect>,
+    write: bool,
+    default: fn() -> Stdio,
+) -> io::Result<Stdio> {
+    match redirect {
+        Some(Redirect::File { path, append }) if write => {
+            let mut options = OpenOptions::new();
+            options.write(true).create(true).append(*append).truncate(!append);
+            options.open(path).map(Stdio::from)
+        }
+        Some(Redirect::File { path, .. }) => File::open(path).map(Stdio::from),
+        Some(Redirect::Var(_) | Redirect::Text(_)) => Ok(Stdio::piped()),
+        None => Ok(default()),
+    }
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str, key: &str) -> Result<Option<Redirect>, ScriptError> {
+        let mut script = Script::new(String::new(), None);
+        script.binding.set_var("dir", Yaml::from_str("/tmp"));
+        Redirect::parse(&mut script, &Binding::hash_from_str(text), key)
+    }
+
+    #[test]
+    fn parse_file() {
+        let file = |path: &str, append| {
+            Some(Redirect::File {
+                path: path.into(),
+                append,
+            })
+        };
+
+        assert_eq!(file("/tmp/out", false), parse("stdout: ${dir}/out", "stdout").unwrap());
+        assert_eq!(
+            file("err", true),
+            parse("stderr: {file: err, append: true}", "stderr").unwrap()
+        );
+        assert_eq!(file("in", false), parse("stdin: {file: in}", "stdin").unwrap());
+        assert_eq!(None, parse("stdout: out", "stderr").unwrap());
+    }
+
+    #[test]
+    fn parse_var() {
+        assert_eq!(Some(Redirect::Var("a".into())), parse("stdin: {var: a}", "stdin").unwrap());
+    }
+
+    #[test]
+    fn parse_text() {
+        let text = Some(Redirect::Text("/tmp/in".into()));
+        assert_eq!(text, parse("stdin: ${dir}/in", "stdin").unwrap());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for e in [
+            ("stdout: {append: true}", "stdout", "expected 'file'"),
+            ("stdout: {file: out, append: 1}", "stdout", "expected boolean for 'append'"),
+        ] {
+            assert_eq!(e.2, parse(e.0, e.1).unwrap_err().to_string(), "{e:?}");
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use super::{Flow, Script, ScriptError};
+use yaml_rust2::Yaml;
+
+// - return: [<expression>]
+pub fn run(s: &mut Script, expr: &Yaml) -> Result<Flow, ScriptError> {
+    Ok(Flow::Return(s.eval_to_yaml(expr)?))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        script.binding.set_var("a", Yaml::Integer(41));
+
+        let flow = super::run(&mut script, &Yaml::from_str("${a + 1}")).unwrap();
+        assert_eq!(Flow::Return(Yaml::Integer(42)), flow);
+    }
+}
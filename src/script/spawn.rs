@@ -0,0 +1,66 @@
+use super::job::{self, Job};
+use super::{exec, Binding, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - spawn: <expression>
+//   [args: <list> | <expression => list>]
+//   [shell: <boolean>]
+//   [pipe: <list of expressions>]
+//   [env: <map>]
+//   [clear_env: <boolean>]
+//   [cwd: <path>]
+//   [stdin: <expression> | {file: <path>} | {var: <name>}]
+//   [stdout: <path> | {file: <path>, [append: <boolean>]} | {var: <name>}]
+//   [stderr: <path> | {file: <path>, [append: <boolean>]} | {var: <name>}]
+//   [as: <name>]
+//
+// Starts the command like exec does, but doesn't wait for it to finish. A handle to it is bound to
+// `as` (default `_`) as a map of id, pid, and command, for wait, kill, and status steps.
+pub fn run(s: &mut Script, expr: &Yaml, step: &Hash) -> Result<(), ScriptError> {
+    let line = s.eval_to_string(expr)?;
+    let (commands, line) = exec::commands(s, line, step)?;
+    let [stdin, stdout, stderr] = exec::redirects(s, step)?;
+    let input = exec::input(s, &stdin);
+
+    let job = Job::start(commands, line.clone(), [&stdin, &stdout, &stderr], input)
+        .map_err(|e| exec::error(&line, e))?;
+    let handle = job::handle(s.jobs.len(), &job);
+    s.jobs.push(job);
+
+    let name = Binding::entry_to_name(step, "as")?.unwrap_or("_");
+    s.binding.set_var(name, handle);
+    Ok(())
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("as: a");
+
+        super::run(&mut script, &Yaml::from_str("sleep 5"), &hash).unwrap();
+        let handle = script.binding.var("a");
+        assert_eq!(0, handle["id"].as_i64().unwrap());
+        assert!(handle["pid"].as_i64().unwrap() > 0);
+        assert_eq!("sleep 5", handle["command"].as_str().unwrap());
+        assert_eq!(1, script.jobs.len());
+
+        super::run(&mut script, &Yaml::from_str("true"), &Hash::new()).unwrap();
+        assert_eq!(1, script.binding.var("_")["id"].as_i64().unwrap());
+    }
+
+    #[test]
+    fn run_missing() {
+        let mut script = Script::new(String::new(), None);
+
+        let err =
+            super::run(&mut script, &Yaml::from_str("no-such-command"), &Hash::new()).unwrap_err();
+        assert!(err.to_string().starts_with("no-such-command: "));
+        assert!(script.jobs.is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use super::{exec, job, Binding, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - status: <handle>
+//   [as: <name>]
+//
+// Binds the status of a spawned command to `as` (default `_`) as a map of running, code, and ok,
+// without waiting for it. The code is null while it's running.
+pub fn run(s: &mut Script, expr: &Yaml, step: &Hash) -> Result<(), ScriptError> {
+    let id = job::id(s, expr, "status")?;
+    let job = &mut s.jobs[id];
+    let status = job.status().map_err(|e| exec::error(&job.line, e))?;
+
+    let code = status.and_then(|s| s.code()).map_or(Yaml::Null, |c| Yaml::Integer(c.into()));
+    let mut hash = Hash::new();
+    hash.insert(Yaml::from_str("running"), Yaml::Boolean(status.is_none()));
+    hash.insert(Yaml::from_str("code"), code);
+    hash.insert(Yaml::from_str("ok"), Yaml::Boolean(status.is_some_and(|s| s.success())));
+
+    let name = Binding::entry_to_name(step, "as")?.unwrap_or("_");
+    s.binding.set_var(name, Yaml::Hash(hash));
+    Ok(())
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::{kill, spawn, wait};
+    use super::*;
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        let handle = Yaml::from_str("${h}");
+        spawn::run(&mut script, &Yaml::from_str("sleep 5"), &Binding::hash_from_str("as: h"))
+            .unwrap();
+
+        super::run(&mut script, &handle, &Hash::new()).unwrap();
+        let res = script.binding.var("_");
+        assert!(res["running"].as_bool().unwrap());
+        assert_eq!(Yaml::Null, res["code"]);
+        assert!(!res["ok"].as_bool().unwrap());
+
+        kill::run(&mut script, &handle).unwrap();
+        super::run(&mut script, &handle, &Hash::new()).unwrap();
+        assert!(!script.binding.var("_")["running"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn run_finished() {
+        let mut script = Script::new(String::new(), None);
+        spawn::run(&mut script, &Yaml::from_str("true"), &Hash::new()).unwrap();
+        wait::run(&mut script, &Yaml::Integer(0), &Hash::new()).unwrap();
+
+        super::run(&mut script, &Yaml::Integer(0), &Binding::hash_from_str("as: a")).unwrap();
+        let res = script.binding.var("a");
+        assert!(!res["running"].as_bool().unwrap());
+        assert_eq!(0, res["code"].as_i64().unwrap());
+        assert!(res["ok"].as_bool().unwrap());
+    }
+}
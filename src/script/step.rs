@@ -1,26 +1,62 @@
-use super::{call, def, each, echo, exec, exit, r#break, r#if, r#while, var, Script};
-use std::io::Error;
-use yaml_rust2::yaml::Hash;
+use super::{call, def, each, echo, env, exec, exit, import, include};
+use super::{kill, local, parallel, spawn, status, task, var, wait};
+use super::{r#break, r#continue, r#if, r#return, r#try, r#while};
+use super::{ErrorKind, Flow, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
 
-pub fn run(s: &mut Script, step: &Hash) -> Result<(), Error> {
+pub fn run(s: &mut Script, step: &Hash) -> Result<Flow, ScriptError> {
     // example: ("echo", 1)
-    let entry = step.iter().next().unwrap();
-    let name = entry.0.as_str().unwrap();
+    let entry =
+        step.iter().next().ok_or_else(|| ScriptError::new(ErrorKind::UnknownStep, "empty step"))?;
+    let name = entry.0.as_str().ok_or_else(|| unknown(entry.0))?;
 
     match name {
         "break" => r#break::run(s, entry.1, step),
         "call" => call::run(s, entry.1, step),
-        "def" => def::run(s, entry.1, step),
+        "continue" => r#continue::run(s, entry.1, step),
+        "def" => def::run(s, entry.1, step).map(next),
         "each" => each::run(s, entry.1, step),
-        "echo" => echo::run(s, entry.1),
-        "exec" => exec::run(s, entry.1, step),
+        "echo" => echo::run(s, entry.1).map(next),
+        "env" => env::run(s, entry.1).map(next),
+        "exec" => exec::run(s, entry.1, step).map(next),
         "exit" => exit::run(s, entry.1),
         "if" => r#if::run(s, entry.1, step),
+        "import" => import::run(s, entry.1, step).map(next),
+        "include" => include::run(s, entry.1),
+        "kill" => kill::run(s, entry.1).map(next),
+        "local" => local::run(s, entry.1).map(next),
+        "parallel" => parallel::run(s, entry.1, step),
+        "return" => r#return::run(s, entry.1),
+        "spawn" => spawn::run(s, entry.1, step).map(next),
+        "status" => status::run(s, entry.1, step).map(next),
+        "task" => task::run(s, entry.1, step).map(next),
+        "try" => r#try::run(s, entry.1, step),
+        "wait" => wait::run(s, entry.1, step).map(next),
         "while" => r#while::run(s, entry.1, step),
-        _ => var::run(s, name, entry.1),
+        _ => var::run(s, name, entry.1).map(next),
     }
 }
 
+// The keys of a step whose values are steps, so that the loader can tell them from other values.
+pub fn nested(name: &str) -> &'static [&'static str] {
+    match name {
+        "def" | "each" | "task" | "while" => &["do"],
+        "if" => &["then", "else"],
+        "parallel" => &["parallel"],
+        "try" => &["try", "catch", "finally"],
+        _ => &[],
+    }
+}
+
+// Most steps go on to the next one.
+fn next(_: ()) -> Flow {
+    Flow::Next
+}
+
+fn unknown(key: &Yaml) -> ScriptError {
+    ScriptError::new(ErrorKind::UnknownStep, format!("unknown step: {key:?}"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::binding::Binding;
@@ -34,4 +70,12 @@ mod tests {
         _ = super::run(&mut script, &Binding::hash_from_str("echo: foo"));
         assert_eq!("foo", script.writer.log[0]);
     }
+
+    #[test]
+    fn run_unknown() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Binding::hash_from_str("1: foo")).unwrap_err();
+        assert_eq!(ErrorKind::UnknownStep, err.kind);
+    }
 }
@@ -0,0 +1,241 @@
+use super::doc::Doc;
+use super::uptodate::{self, Check};
+use super::{call, def, Binding, ErrorKind, Flow, Frame, Script, ScriptError};
+use std::slice;
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - task: <name>
+//   [description: <string>]
+//   [needs: <list of names>]
+//   [inputs: <list of patterns>]
+//   [outputs: <list of patterns>]
+//   do: <steps>
+//
+// Defines a task, which is a proc that's run after the tasks it needs, e.g. by `ys make.ys build`,
+// unless it's up to date (see `uptodate`).
+pub fn run(s: &mut Script, name: &Yaml, step: &Hash) -> Result<(), ScriptError> {
+    Binding::entry_to_list(step, "do")?;
+    description(step)?;
+    Binding::entry_to_list_or_empty(step, "inputs")?;
+    Binding::entry_to_list_or_empty(step, "outputs")?;
+    needs(step)?;
+
+    let proc = def::proc(s, step);
+    s.binding.set_proc(Binding::yaml_to_name(name, "task")?, proc);
+
+    Ok(())
+}
+
+fn needs(step: &Hash) -> Result<Vec<String>, ScriptError> {
+    Binding::entry_to_list_or_empty(step, "needs")?
+        .iter()
+        .map(|n| Binding::yaml_to_name(n, "needs").map(String::from))
+        .collect()
+}
+
+fn description(step: &Hash) -> Result<Option<&str>, ScriptError> {
+    match step.get(&Yaml::from_str("description")) {
+        Some(Yaml::String(s)) => Ok(Some(s)),
+        Some(_) => Err(ScriptError::type_mismatch("string", "description")),
+        None => Ok(None),
+    }
+}
+
+// The names and descriptions of the tasks that the documents define, by their own steps (not in
+// other steps, or files that they import), before they're run, e.g. for the usage of the script.
+pub fn list<'a>(docs: &[Doc<'a>]) -> Vec<(&'a str, Option<&'a str>)> {
+    docs.iter()
+        .flat_map(|d| d.steps)
+        .filter_map(Yaml::as_hash)
+        .filter_map(|step| match step.front() {
+            Some((key, Yaml::String(name))) if key.as_str() == Some("task") => {
+                Some((name.as_str(), description(step).ok().flatten()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Whether a proc was defined by a task step.
+pub fn is_task(s: &Script, name: &str) -> bool {
+    s.binding.procs.get(name).is_some_and(|p| p.def.contains_key(&Yaml::from_str("task")))
+}
+
+// Whether the script has defined any tasks.
+pub fn any(s: &Script) -> bool {
+    s.binding.procs.keys().any(|name| is_task(s, name))
+}
+
+//-----------------------------------------------------------------------------
+
+// Runs the tasks, each after the tasks it needs, and each only once, unless it's up to date.
+pub fn run_tasks(s: &mut Script, names: &[String]) -> Result<Flow, ScriptError> {
+    let mut order = Vec::new();
+    let mut visiting = Vec::new();
+
+    for name in names {
+        let name = full_name(s, None, name)?;
+        sort(s, &name, &mut visiting, &mut order)?;
+    }
+
+    for name in order {
+        let task = s.binding.procs[&name].clone();
+        let hash = match uptodate::check(s, &name, &task.def)? {
+            Check::Skip => continue,
+            Check::Run(hash) => hash,
+        };
+
+        match call::run_proc(s, &name, Frame::new())? {
+            Flow::Return(_) => (),
+            flow => return Ok(flow),
+        }
+
+        if let Some(hash) = hash {
+            uptodate::save(s, &name, &hash)?;
+        }
+    }
+
+    Ok(Flow::Next)
+}
+
+// Adds a task to the order after the tasks it needs (a topological sort, by depth first search),
+// unless it's already there.
+fn sort(
+    s: &Script,
+    name: &str,
+    visiting: &mut Vec<String>,
+    order: &mut Vec<String>,
+) -> Result<(), ScriptError> {
+    if order.iter().any(|n| n == name) {
+        return Ok(());
+    }
+
+    if visiting.iter().any(|n| n == name) {
+        let cycle = [&visiting[..], slice::from_ref(&name.to_string())].concat().join(" -> ");
+        return Err(ScriptError::new(ErrorKind::Flow, format!("cycle: {cycle}")));
+    }
+
+    let proc = s.binding.procs[name].clone();
+    visiting.push(name.into());
+
+    for need in needs(&proc.def)? {
+        let need = full_name(s, Some(name), &need)?;
+        sort(s, &need, visiting, order)?;
+    }
+
+    visiting.pop();
+    order.push(name.into());
+    Ok(())
+}
+
+// The full name of a task: in the namespace of the task that needs it, if it's defined there, or
+// else as it is.
+fn full_name(s: &Script, from: Option<&str>, name: &str) -> Result<String, ScriptError> {
+    let namespace = from.and_then(|f| f.rsplit_once('.')).map(|(ns, _)| ns);
+    let namespaced = namespace.map(|ns| format!("{ns}.{name}"));
+
+    namespaced
+        .into_iter()
+        .chain([name.to_string()])
+        .find(|n| is_task(s, n))
+        .ok_or_else(|| ScriptError::new(ErrorKind::Argument, format!("unknown task '{name}'")))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TASKS: &str = "
+- task: compile
+  do: [echo: compile]
+- task: build
+  needs: [compile]
+  do: [echo: build]
+- task: test
+  needs: [compile, build]
+  do: [echo: test]
+- def: helper
+  do: []
+";
+
+    fn run_tasks(text: &str, names: &[&str]) -> Result<Vec<String>, ScriptError> {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script.run_str(text).unwrap();
+
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        super::run_tasks(&mut script, &names)?;
+        Ok(script.writer.log)
+    }
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        script.run_str(TASKS).unwrap();
+
+        assert!(is_task(&script, "build"));
+        assert!(!is_task(&script, "helper"));
+        assert!(any(&script));
+    }
+
+    #[test]
+    fn run_errors() {
+        let mut script = Script::new(String::new(), None);
+
+        for e in [
+            ("{task: a}", "expected 'do'"),
+            ("{task: a, needs: b, do: []}", "expected list for 'needs'"),
+            ("{task: a, needs: [[]], do: []}", "expected name for 'needs'"),
+            ("{task: a, description: [], do: []}", "expected string for 'description'"),
+            ("{task: [], do: []}", "expected name for 'task'"),
+        ] {
+            let hash = Binding::hash_from_str(e.0);
+            let name = &hash[&Yaml::from_str("task")];
+            let err = super::run(&mut script, name, &hash).unwrap_err();
+            assert_eq!(e.1, err.to_string(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn run_tasks_order() {
+        assert_eq!(vec!["compile", "build", "test"], run_tasks(TASKS, &["test"]).unwrap());
+        assert_eq!(vec!["compile", "build", "test"], run_tasks(TASKS, &["build", "test"]).unwrap());
+        assert_eq!(vec!["compile"], run_tasks(TASKS, &["compile", "compile"]).unwrap());
+    }
+
+    #[test]
+    fn run_tasks_unknown() {
+        for names in [["nope"], ["helper"]] {
+            let err = run_tasks(TASKS, &names).unwrap_err();
+            assert_eq!(ErrorKind::Argument, err.kind);
+            assert_eq!(format!("unknown task '{}'", names[0]), err.to_string());
+        }
+
+        let text = "[{task: a, needs: [b], do: []}]";
+        assert_eq!("unknown task 'b'", run_tasks(text, &["a"]).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn run_tasks_cycle() {
+        let text = "
+- {task: a, needs: [b], do: []}
+- {task: b, needs: [c], do: []}
+- {task: c, needs: [a], do: []}
+";
+
+        let err = run_tasks(text, &["a"]).unwrap_err();
+        assert_eq!(ErrorKind::Flow, err.kind);
+        assert_eq!("cycle: a -> b -> c -> a", err.to_string());
+    }
+
+    #[test]
+    fn run_tasks_exit() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script.run_str("[{task: a, do: [exit: 2]}, {task: b, needs: [a], do: [echo: b]}]").unwrap();
+
+        let flow = super::run_tasks(&mut script, &["b".into()]).unwrap();
+        assert_eq!(Flow::Exit(2), flow);
+        assert!(script.writer.log.is_empty());
+    }
+}
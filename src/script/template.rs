@@ -0,0 +1,182 @@
+use super::{ErrorKind, ScriptError};
+use std::ops::Range;
+
+// Finds the expressions in a text, e.g. `${len(x) + 1}`, by their byte ranges, including the `${`
+// and `}`. An expression ends at the `}` that closes it, so it can have brackets, braces, and
+// strings in single or double quotes (which can have any of those in them, and `\` escapes). `$${`
+// is a literal `${`, not an expression.
+pub fn expressions(text: &str) -> Result<Vec<Range<usize>>, ScriptError> {
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+
+    while let Some(i) = text[pos..].find("${").map(|i| pos + i) {
+        if text[..i].ends_with('$') {
+            pos = i + 2;
+            continue;
+        }
+
+        let end = close(text, i)?;
+        ranges.push(i..end);
+        pos = end;
+    }
+
+    Ok(ranges)
+}
+
+// The end of the expression that starts at `start`.
+fn close(text: &str, start: usize) -> Result<usize, ScriptError> {
+    // Opening brackets, and where they are, innermost last.
+    let mut open = vec![('{', start)];
+    let mut quote: Option<(char, usize)> = None;
+    let mut escaped = false;
+
+    for (i, c) in text[start + 2..].char_indices().map(|(i, c)| (start + 2 + i, c)) {
+        if let Some((q, _)) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => (),
+            }
+            continue;
+        }
+
+        match c {
+            '\'' | '"' => quote = Some((c, i)),
+            '(' | '[' | '{' => open.push((c, i)),
+            ')' | ']' | '}' => {
+                let (o, _) = open.pop().unwrap_or_default();
+
+                if (o, c) != ('(', ')') && (o, c) != ('[', ']') && (o, c) != ('{', '}') {
+                    return Err(error(text, i, &format!("unexpected '{c}'")));
+                }
+
+                if open.is_empty() {
+                    if text[start + 2..i].trim().is_empty() {
+                        return Err(error(text, start, "empty expression"));
+                    }
+                    return Ok(i + 1);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    match (quote, open.pop()) {
+        (Some((q, i)), _) => Err(error(text, i, &format!("unclosed {q} quote"))),
+        (_, Some((_, i))) if i == start => Err(error(text, i, "unclosed '${'")),
+        (_, Some((o, i))) => Err(error(text, i, &format!("unclosed '{o}'"))),
+        (None, None) => unreachable!(),
+    }
+}
+
+// Takes the strings out of an expression, since eval has no escapes: each is replaced with a name
+// (`__str0`, ...), and returned with its value. `\n` and `\t` are a newline and a tab, and `\`
+// before any other character is that character.
+pub fn literals(expr: &str) -> (String, Vec<(String, String)>) {
+    let mut buf = String::new();
+    let mut strings = Vec::new();
+    let mut chars = expr.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\'' && c != '"' {
+            buf.push(c);
+            continue;
+        }
+
+        let mut val = String::new();
+
+        while let Some(d) = chars.next() {
+            match d {
+                '\\' => match chars.next() {
+                    Some('n') => val.push('\n'),
+                    Some('t') => val.push('\t'),
+                    Some(e) => val.push(e),
+                    None => (),
+                },
+                _ if d == c => break,
+                _ => val.push(d),
+            }
+        }
+
+        let name = format!("__str{}", strings.len());
+        buf.push_str(&name);
+        strings.push((name, val));
+    }
+
+    (buf, strings)
+}
+
+// Replaces each `$${` with `${`, in the text around expressions.
+pub fn unescape(text: &str) -> String {
+    text.replace("$${", "${")
+}
+
+// Columns count characters, from 1.
+fn error(text: &str, i: usize, msg: &str) -> ScriptError {
+    let col = text[..i].chars().count() + 1;
+    ScriptError::new(ErrorKind::Expression, format!("{text}: {msg} at column {col}"))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str) -> Vec<&str> {
+        super::expressions(text).unwrap().into_iter().map(|r| &text[r]).collect()
+    }
+
+    #[test]
+    fn expressions() {
+        assert!(find("no expressions { } $ {x}").is_empty());
+        assert_eq!(vec!["${x}", "${y + 1}"], find("a ${x} b ${y + 1}"));
+        assert_eq!(vec!["${len(x)}"], find("${len(x)}"));
+        assert_eq!(vec!["${a > (b + 1)}"], find("${a > (b + 1)}"));
+        assert_eq!(vec!["${x[0]}"], find("${x[0]}:"));
+        assert_eq!(vec![r#"${"a: }"}"#], find(r#"${"a: }"}"#));
+        assert_eq!(vec!["${'(' + x}"], find("${'(' + x}"));
+        assert_eq!(vec!["${f({})}"], find("${f({})}"));
+        assert_eq!(vec!["${é}", "${x}"], find("é${é}${x}"));
+        assert_eq!(vec![r#"${"a\"}"}"#, "${x}"], find(r#"${"a\"}"} ${x}"#));
+        assert_eq!(vec![r"${'a\\'}"], find(r"${'a\\'}"));
+    }
+
+    #[test]
+    fn literals() {
+        let (expr, strings) = super::literals(r#"len("a\"b") + 'c\\\n' + x"#);
+        assert_eq!("len(__str0) + __str1 + x", expr);
+        assert_eq!(
+            vec![
+                ("__str0".into(), r#"a"b"#.into()),
+                ("__str1".into(), "c\\\n".into())
+            ],
+            strings
+        );
+    }
+
+    #[test]
+    fn expressions_escaped() {
+        assert!(find("$${x}").is_empty());
+        assert_eq!(vec!["${y}"], find("$${x} ${y}"));
+        assert_eq!("${x} $$ $", unescape("$${x} $$ $"));
+    }
+
+    #[test]
+    fn expressions_errors() {
+        for e in [
+            ("a ${x", "a ${x: unclosed '${' at column 3"),
+            ("${f(x}", "${f(x}: unexpected '}' at column 6"),
+            ("${x)}", "${x)}: unexpected ')' at column 4"),
+            ("${len(x}", "${len(x}: unexpected '}' at column 8"),
+            ("${f(x", "${f(x: unclosed '(' at column 4"),
+            ("é ${'a}", "é ${'a}: unclosed ' quote at column 5"),
+            ("${ }", "${ }: empty expression at column 1"),
+        ] {
+            let err = super::expressions(e.0).unwrap_err();
+            assert_eq!(ErrorKind::Expression, err.kind);
+            assert_eq!(e.1, err.to_string(), "{e:?}");
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use super::Script;
+use std::{env, fs, path::PathBuf, process};
+
+// A directory of files for a test, named for it, which is removed when it's dropped.
+pub struct Dir(pub PathBuf);
+
+impl Dir {
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = Self(env::temp_dir().join(format!("ys-{name}-{}", process::id())));
+        fs::create_dir_all(&dir.0).unwrap();
+
+        for (path, text) in files {
+            dir.write(path, text);
+        }
+
+        dir
+    }
+
+    // Writes a file, and the directories it's in.
+    pub fn write(&self, path: &str, text: &str) {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    pub fn path(&self, path: &str) -> String {
+        self.0.join(path).display().to_string()
+    }
+
+    // A script for one of the files, which logs what it writes.
+    pub fn script(&self, path: &str) -> Script {
+        Script::new(self.path(path), Some(Vec::new()))
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        _ = fs::remove_dir_all(&self.0);
+    }
+}
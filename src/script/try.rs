@@ -0,0 +1,143 @@
+use super::{Binding, Flow, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - try: <steps>
+//   [catch: <steps>]
+//   [as: <name>]
+//   [finally: <steps>]
+//
+// The error is bound to `as` (default `error`) for the catch steps, as a map of message, kind,
+// location, and the exit code of a failed command. The finally steps are always run, however the
+// others end.
+pub fn run(s: &mut Script, steps: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
+    let steps = steps.as_vec().ok_or_else(|| ScriptError::type_mismatch("list", "try"))?;
+    let catch = catch_steps(step)?;
+    let name = Binding::entry_to_name(step, "as")?.unwrap_or("error");
+    let finally = Binding::entry_to_list_or_empty(step, "finally")?;
+
+    let res = match (s.run_steps(steps), catch) {
+        (Err(e), Some(catch)) => {
+            s.binding.set_var(name, error_to_yaml(&e));
+            s.run_steps(catch)
+        }
+        (res, _) => res,
+    };
+
+    match s.run_steps(finally)? {
+        Flow::Next => res,
+        flow => Ok(flow),
+    }
+}
+
+// Errors aren't caught if there's no catch, only if it's empty.
+fn catch_steps(step: &Hash) -> Result<Option<&[Yaml]>, ScriptError> {
+    match step.contains_key(&Yaml::from_str("catch")) {
+        true => Binding::entry_to_list(step, "catch").map(Some),
+        false => Ok(None),
+    }
+}
+
+fn error_to_yaml(e: &ScriptError) -> Yaml {
+    let location = e.location.as_ref().map(|l| Yaml::String(l.to_string()));
+    let mut hash = Hash::new();
+    hash.insert(Yaml::from_str("message"), Yaml::String(e.message.clone()));
+    hash.insert(Yaml::from_str("kind"), Yaml::String(e.kind.to_string()));
+    hash.insert(Yaml::from_str("location"), location.unwrap_or(Yaml::Null));
+    hash.insert(Yaml::from_str("code"), e.code.map_or(Yaml::Null, |c| Yaml::Integer(c.into())));
+
+    Yaml::Hash(hash)
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::ErrorKind;
+    use super::*;
+
+    fn try_steps() -> Yaml {
+        Yaml::Array(vec![Yaml::Hash(Binding::hash_from_str("exit: foo"))])
+    }
+
+    #[test]
+    fn run_catch() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("catch: [echo: '${error.kind}: ${error.message}']");
+
+        super::run(&mut script, &try_steps(), &hash).unwrap();
+        assert_eq!("type_mismatch: expected number: \"foo\"", script.writer.log[0]);
+    }
+
+    #[test]
+    fn run_catch_as() {
+        let mut script = Script::new("foo.ys".into(), None);
+
+        #[rustfmt::skip]
+        let lines = [
+            "- try:",
+            "    - echo: ${x +}",
+            "  catch: []",
+            "  as: e",
+        ];
+
+        script.run_str(&lines.join("\n")).unwrap();
+        let err = script.binding.var("e");
+        assert_eq!("expression", err["kind"].as_str().unwrap());
+        assert_eq!("foo.ys:2:7", err["location"].as_str().unwrap());
+    }
+
+    #[test]
+    fn run_catch_code() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        script
+            .run_str("[{try: [{exec: 'false', check: true}], catch: [echo: '${error.code}']}]")
+            .unwrap();
+
+        assert_eq!("1", script.writer.log[0]);
+    }
+
+    #[test]
+    fn run_finally() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("finally: [echo: done]");
+
+        let err = super::run(&mut script, &try_steps(), &hash).unwrap_err();
+        assert_eq!(ErrorKind::TypeMismatch, err.kind);
+        assert_eq!(vec!["done"], script.writer.log);
+    }
+
+    #[test]
+    fn run_finally_flow() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{catch: [echo: caught], finally: [echo: done]}");
+        let steps = Yaml::Array(vec![Yaml::Hash(Binding::hash_from_str("break: true"))]);
+
+        let flow = super::run(&mut script, &steps, &hash).unwrap();
+        assert_eq!(
+            Flow::Break {
+                label: None,
+                message: "(break)".into()
+            },
+            flow
+        );
+        assert_eq!(vec!["done"], script.writer.log);
+    }
+
+    #[test]
+    fn run_catch_error() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str("{catch: [exit: bar], finally: [echo: done]}");
+
+        let err = super::run(&mut script, &try_steps(), &hash).unwrap_err();
+        assert_eq!("expected number: \"bar\"", err.to_string());
+        assert_eq!(vec!["done"], script.writer.log);
+    }
+
+    #[test]
+    fn run_not_list() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Yaml::Null, &Hash::new()).unwrap_err();
+        assert_eq!(ErrorKind::TypeMismatch, err.kind);
+    }
+}
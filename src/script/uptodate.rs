@@ -0,0 +1,228 @@
+use super::{glob, Binding, ErrorKind, Script, ScriptError};
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::PathBuf, time::SystemTime};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+const CACHE: &str = ".ys-cache";
+
+pub enum Check {
+    Skip,
+    // With the hash of the inputs, if the task has any, to save once it's run.
+    Run(Option<String>),
+}
+
+// Checks whether a task is up to date, so that it can be skipped: if its outputs are all newer
+// than its inputs, or if the hash of its inputs is the same as when it was last run. Writes why
+// it's run, or not, if it has inputs or outputs.
+//
+// [inputs: <list of patterns>]
+// [outputs: <list of patterns>]
+//
+// Patterns (see `glob`) are relative to the script, like includes.
+pub fn check(s: &mut Script, name: &str, task: &Hash) -> Result<Check, ScriptError> {
+    let inputs = patterns(s, task, "inputs")?;
+    let outputs = patterns(s, task, "outputs")?;
+
+    if inputs.is_none() && outputs.is_none() {
+        return Ok(Check::Run(None));
+    }
+
+    let inputs: Vec<PathBuf> = inputs.into_iter().flatten().flat_map(|p| glob::files(&p)).collect();
+    let hash = hash(&inputs).map_err(|e| io_error(name, e))?;
+    let cache = s.resolve(&format!("{CACHE}/{}", file_name(name)));
+    let cached = fs::read_to_string(&cache).ok().map(|c| c.trim().to_string());
+
+    let mut reason = None;
+
+    if let Some(outputs) = &outputs {
+        if let Some(missing) = outputs.iter().find(|p| glob::files(p).is_empty()) {
+            return Ok(run(s, name, format!("'{missing}' is missing"), hash));
+        }
+
+        reason = newer(&inputs, outputs).map_err(|e| io_error(name, e))?;
+
+        if reason.is_none() {
+            s.writer.write_err(format!("{name}: up to date"));
+            return Ok(Check::Skip);
+        }
+    }
+
+    if cached.as_ref() == Some(&hash) {
+        s.writer.write_err(format!("{name}: up to date (inputs unchanged)"));
+        return Ok(Check::Skip);
+    }
+
+    let reason = reason.unwrap_or_else(|| match cached {
+        Some(_) => "inputs changed".into(),
+        None => "first run".into(),
+    });
+    Ok(run(s, name, reason, hash))
+}
+
+fn run(s: &mut Script, name: &str, reason: String, hash: String) -> Check {
+    s.writer.write_err(format!("{name}: running ({reason})"));
+    Check::Run(Some(hash))
+}
+
+// Saves the hash of the inputs of a task that's been run.
+pub fn save(s: &Script, name: &str, hash: &str) -> Result<(), ScriptError> {
+    let dir = s.resolve(CACHE);
+    let path = format!("{dir}/{}", file_name(name));
+
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(path, format!("{hash}\n")))
+        .map_err(|e| io_error(name, e))
+}
+
+fn patterns(s: &mut Script, task: &Hash, key: &str) -> Result<Option<Vec<String>>, ScriptError> {
+    if !task.contains_key(&Yaml::from_str(key)) {
+        return Ok(None);
+    }
+
+    let list = Binding::entry_to_list(task, key)?;
+    let patterns = list.iter().map(|p| s.eval_to_string(p)).collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Some(patterns.iter().map(|p| s.resolve(p)).collect()))
+}
+
+// If an input is newer than an output, says so.
+fn newer(inputs: &[PathBuf], outputs: &[String]) -> io::Result<Option<String>> {
+    let mut oldest: Option<(SystemTime, PathBuf)> = None;
+
+    for pattern in outputs {
+        for file in glob::files(pattern) {
+            let time = fs::metadata(&file)?.modified()?;
+
+            if oldest.as_ref().is_none_or(|o| time < o.0) {
+                oldest = Some((time, file));
+            }
+        }
+    }
+
+    let Some((oldest, output)) = oldest else {
+        return Ok(None);
+    };
+
+    for input in inputs {
+        if fs::metadata(input)?.modified()? > oldest {
+            let (input, output) = (input.display(), output.display());
+            return Ok(Some(format!("'{input}' is newer than '{output}'")));
+        }
+    }
+
+    Ok(None)
+}
+
+// A SHA-256 hash of the paths and contents of files, in hex. Each is prefixed with its length, so
+// that a path and contents can't run into the next.
+fn hash(files: &[PathBuf]) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    for file in files {
+        for bytes in [file.as_os_str().as_encoded_bytes(), &fs::read(file)?] {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        }
+    }
+
+    Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+fn file_name(name: &str) -> String {
+    name.replace(|c: char| !c.is_alphanumeric() && !"._-".contains(c), "_")
+}
+
+fn io_error(name: &str, err: io::Error) -> ScriptError {
+    ScriptError::new(ErrorKind::Io, format!("{name}: {err}"))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::task;
+    use super::super::test_util::Dir;
+    use super::*;
+    use std::{thread, time::Duration};
+
+    const TASKS: &str = "
+- {task: build, inputs: ['src/*'], outputs: [out], do: [{exec: 'cp src/a out', cwd: '${dir}'}]}
+- {task: test, inputs: ['src/*', out], do: [echo: test]}
+- {task: other, do: []}
+";
+
+    fn dir(name: &str) -> Dir {
+        Dir::new(name, &[("src/a", "1")])
+    }
+
+    // Runs tasks, and returns what was written, with paths relative to the directory.
+    fn run(dir: &Dir, names: &[&str]) -> Vec<String> {
+        let mut script = dir.script("make.ys");
+        script.binding.set_var("dir", Yaml::String(dir.0.display().to_string()));
+        script.run_str(TASKS).unwrap();
+
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        task::run_tasks(&mut script, &names).unwrap();
+        let prefix = format!("{}/", dir.0.display());
+        script.writer.log.iter().map(|l| l.replace(&prefix, "")).collect()
+    }
+
+    #[test]
+    fn check_outputs() {
+        let dir = dir("uptodate-outputs");
+
+        assert_eq!(vec!["build: running ('out' is missing)"], run(&dir, &["build"]));
+        assert_eq!(vec!["build: up to date"], run(&dir, &["build"]));
+
+        // Newer, but the same.
+        thread::sleep(Duration::from_millis(20));
+        dir.write("src/a", "1");
+        assert_eq!(vec!["build: up to date (inputs unchanged)"], run(&dir, &["build"]));
+
+        dir.write("src/a", "2");
+        assert_eq!(vec!["build: running ('src/a' is newer than 'out')"], run(&dir, &["build"]));
+        assert_eq!("2", fs::read_to_string(dir.0.join("out")).unwrap());
+    }
+
+    #[test]
+    fn check_inputs() {
+        let dir = dir("uptodate-inputs");
+
+        assert_eq!(
+            vec![
+                "build: running ('out' is missing)",
+                "test: running (first run)",
+                "test"
+            ],
+            run(&dir, &["build", "test"])
+        );
+        assert_eq!(vec!["test: up to date (inputs unchanged)"], run(&dir, &["test"]));
+
+        dir.write("src/b", "");
+        assert_eq!(vec!["test: running (inputs changed)", "test"], run(&dir, &["test"]));
+    }
+
+    #[test]
+    fn check_none() {
+        let dir = dir("uptodate-none");
+
+        assert!(run(&dir, &["other"]).is_empty());
+        assert!(!dir.0.join(CACHE).exists());
+    }
+
+    #[test]
+    fn hash() {
+        // It's SHA-256, which doesn't change with the build of ys.
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(empty, super::hash(&[]).unwrap());
+    }
+
+    #[test]
+    fn check_not_list() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{task: a, outputs: out, do: []}");
+
+        let err = super::check(&mut script, "a", &hash).err().unwrap();
+        assert_eq!("expected list for 'outputs'", err.to_string());
+    }
+}
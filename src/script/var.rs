@@ -1,10 +1,10 @@
-use super::Script;
-use std::io::Error;
+use super::{Script, ScriptError};
 use yaml_rust2::Yaml;
 
-pub fn run<S: Into<String>>(s: &mut Script, name: S, yaml: &Yaml) -> Result<(), Error> {
+pub fn run<S: Into<String>>(s: &mut Script, name: S, yaml: &Yaml) -> Result<(), ScriptError> {
     // ???: Need validation. Name must be an identifier.
-    s.binding.set_var(name, s.binding.eval_to_yaml(yaml));
+    let val = s.eval_to_yaml(yaml)?;
+    s.binding.set_var(name, val);
     Ok(())
 }
 
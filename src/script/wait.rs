@@ -0,0 +1,74 @@
+use super::{exec, job, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
+
+// - wait: <handle>
+//   [timeout: <seconds>]
+//   [as: <name>]
+//   [check: <boolean>]
+//
+// Waits for a spawned command to finish, and binds its result like exec does. After `timeout`
+// seconds, the command is killed, and it's an error.
+pub fn run(s: &mut Script, expr: &Yaml, step: &Hash) -> Result<(), ScriptError> {
+    let id = job::id(s, expr, "wait")?;
+    let timeout = exec::timeout(s, step)?;
+    let job = &mut s.jobs[id];
+    let output = job.wait(timeout, &mut s.writer).map_err(|e| exec::error(&job.line, e))?;
+    let (line, vars) = (job.line.clone(), job.vars.clone());
+
+    exec::finish(s, step, &line, vars, output)
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::{spawn, Binding, ErrorKind};
+    use super::*;
+
+    fn spawn(script: &mut Script, line: &str, step: &str) {
+        spawn::run(script, &Yaml::from_str(line), &Binding::hash_from_str(step)).unwrap();
+    }
+
+    #[test]
+    fn run() {
+        let mut script = Script::new(String::new(), None);
+        spawn(&mut script, "sh -c 'echo 1; exit 2'", "{stdout: {var: out}, as: h}");
+
+        super::run(&mut script, &Yaml::from_str("${h}"), &Binding::hash_from_str("as: a")).unwrap();
+        let res = script.binding.var("a");
+        assert_eq!(2, res["code"].as_i64().unwrap());
+        assert_eq!("1", res["stdout"].as_str().unwrap());
+        assert!(!res["ok"].as_bool().unwrap());
+        assert_eq!("1", script.binding.var("out").as_str().unwrap());
+    }
+
+    #[test]
+    fn run_check() {
+        let mut script = Script::new(String::new(), None);
+        spawn(&mut script, "false", "{}");
+
+        let hash = Binding::hash_from_str("check: true");
+        let err = super::run(&mut script, &Yaml::from_str("${_}"), &hash).unwrap_err();
+        assert_eq!(Some(1), err.code);
+        assert_eq!("false: exit status: 1", err.to_string());
+    }
+
+    #[test]
+    fn run_timeout() {
+        let mut script = Script::new(String::new(), None);
+        spawn(&mut script, "sleep 5", "{}");
+
+        let hash = Binding::hash_from_str("timeout: 0.1");
+        let err = super::run(&mut script, &Yaml::Integer(0), &hash).unwrap_err();
+        assert_eq!(ErrorKind::Exec, err.kind);
+        assert_eq!("sleep 5: timed out after 0.1s", err.to_string());
+    }
+
+    #[test]
+    fn run_unknown() {
+        let mut script = Script::new(String::new(), None);
+
+        let err = super::run(&mut script, &Yaml::Integer(0), &Hash::new()).unwrap_err();
+        assert_eq!(ErrorKind::Argument, err.kind);
+    }
+}
@@ -1,26 +1,28 @@
-use super::{Binding, Script};
-use std::io::{Error, ErrorKind::Interrupted};
-use yaml_rust2::{
-    yaml::{Array, Hash},
-    Yaml,
-};
+use super::{flow, Binding, Flow, Script, ScriptError};
+use yaml_rust2::{yaml::Hash, Yaml};
 
 // - while: <condition>
+//   [label: <name>]
 //   do:
 //     <steps>
-pub fn run(s: &mut Script, cond: &Yaml, step: &Hash) -> Result<(), Error> {
-    match run_steps(s, cond, &Binding::entry_to_list(step, "do")) {
-        Err(e) if e.kind() == Interrupted => Ok(()),
-        r => r,
-    }
+pub fn run(s: &mut Script, cond: &Yaml, step: &Hash) -> Result<Flow, ScriptError> {
+    let label = Binding::entry_to_name(step, "label")?;
+    run_steps(s, cond, Binding::entry_to_list(step, "do")?, label)
 }
 
-pub fn run_steps(s: &mut Script, cond: &Yaml, steps: &Array) -> Result<(), Error> {
-    while s.binding.is_truthy(cond) {
-        s.run_steps(&steps)?;
+pub fn run_steps(
+    s: &mut Script,
+    cond: &Yaml,
+    steps: &[Yaml],
+    label: Option<&str>,
+) -> Result<Flow, ScriptError> {
+    while s.is_truthy(cond)? {
+        if let Some(flow) = flow::run_loop_steps(s, steps, label)? {
+            return Ok(flow);
+        }
     }
 
-    Ok(())
+    Ok(Flow::Next)
 }
 
 //=============================================================================
@@ -39,4 +41,51 @@ mod tests {
         super::run(&mut script, &Yaml::from_str("${a == 1}"), &hash).unwrap();
         assert_eq!(42, script.binding.var("a").as_i64().unwrap());
     }
+
+    #[test]
+    fn run_continue() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash =
+            Binding::hash_from_str("do: [a: '${a + 1}', continue: '${a == 2}', echo: '${a}']");
+        script.binding.set_var("a", Yaml::Integer(0));
+
+        super::run(&mut script, &Yaml::from_str("${a < 3}"), &hash).unwrap();
+        assert_eq!(vec!["1", "3"], script.writer.log);
+    }
+
+    #[test]
+    fn run_label() {
+        let mut script = Script::new(String::new(), Some(Vec::new()));
+        let hash = Binding::hash_from_str(
+            "{label: outer, do: [{while: true, do: [{break: true, label: outer}]}, echo: foo]}",
+        );
+
+        let flow = super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap();
+        assert_eq!(Flow::Next, flow);
+        assert!(script.writer.log.is_empty());
+    }
+
+    #[test]
+    fn run_outer_label() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("do: [{break: true, label: outer}]");
+
+        let flow = super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap();
+        assert_eq!(
+            Flow::Break {
+                label: Some("outer".into()),
+                message: "(break)".into()
+            },
+            flow
+        );
+    }
+
+    #[test]
+    fn run_missing_do() {
+        let mut script = Script::new(String::new(), None);
+        let hash = Binding::hash_from_str("{label: a}");
+
+        let err = super::run(&mut script, &Yaml::from_str("true"), &hash).unwrap_err();
+        assert_eq!("expected 'do'", err.to_string());
+    }
 }
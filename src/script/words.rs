@@ -0,0 +1,77 @@
+use super::{ErrorKind, ScriptError};
+use std::str::Chars;
+
+// Splits a command line into words the way a POSIX shell does, but without any expansion.
+// Whitespace separates words, and single quotes keep everything in them. Double quotes keep
+// everything but escaped `"`, `\`, `$` and `` ` ``, and a backslash outside of quotes keeps the
+// next character.
+pub fn split(line: &str) -> Result<Vec<String>, ScriptError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => words.extend(word.take()),
+            '\'' | '"' => quoted(&mut chars, word.get_or_insert_with(String::new), c)
+                .ok_or_else(|| error(line, &format!("unterminated {c} quote")))?,
+            '\\' => match chars.next() {
+                Some('\n') => (),
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => word.get_or_insert_with(String::new).push('\\'),
+            },
+            _ => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    Ok(words)
+}
+
+// Adds the rest of a quoted string to a word. Returns None if the quote isn't closed.
+fn quoted(chars: &mut Chars, word: &mut String, quote: char) -> Option<()> {
+    loop {
+        match (quote, chars.next()?) {
+            (_, c) if c == quote => return Some(()),
+            ('"', '\\') => match chars.next()? {
+                '\n' => (),
+                c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                c => word.extend(['\\', c]),
+            },
+            (_, c) => word.push(c),
+        }
+    }
+}
+
+fn error(line: &str, msg: &str) -> ScriptError {
+    ScriptError::new(ErrorKind::Exec, format!("{line}: {msg}"))
+}
+
+//=============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        for e in [
+            ("", vec![]),
+            ("  ls  -l ", vec!["ls", "-l"]),
+            (r#"git commit -m "two words""#, vec!["git", "commit", "-m", "two words"]),
+            (r#"echo 'a "b"' c'd'e"#, vec!["echo", r#"a "b""#, "cde"]),
+            (r#"echo "a \"b\" \n""#, vec!["echo", r#"a "b" \n"#]),
+            (r"echo a\ b \'c", vec!["echo", "a b", "'c"]),
+            (r#"echo '' """#, vec!["echo", "", ""]),
+        ] {
+            assert_eq!(e.1, super::split(e.0).unwrap(), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn split_unterminated() {
+        let err = super::split("echo 'a").unwrap_err();
+        assert_eq!(ErrorKind::Exec, err.kind);
+        assert_eq!("echo 'a: unterminated ' quote", err.to_string());
+    }
+}
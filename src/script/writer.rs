@@ -1,18 +1,31 @@
+type WriteFn = fn(&mut Writer, val: String);
+
 pub struct Writer {
     pub log: Vec<String>,
-    write_fn: fn(&mut Writer, val: String),
+    write_fn: WriteFn,
+    write_err_fn: WriteFn,
 }
 
 impl Writer {
     pub fn new(log_opt: Option<Vec<String>>) -> Writer {
-        let write_fn = match log_opt {
-            Some(_) => Self::write_log,
-            None => Self::write_stdout,
+        let (write_fn, write_err_fn): (WriteFn, WriteFn) = match log_opt {
+            Some(_) => (Self::write_log, Self::write_log),
+            None => (Self::write_stdout, Self::write_stderr),
         };
 
         Self {
-            log: log_opt.unwrap_or(Vec::new()),
+            log: log_opt.unwrap_or_default(),
             write_fn,
+            write_err_fn,
+        }
+    }
+
+    // A writer that writes the same way, but logs separately, e.g. for a branch of a parallel step.
+    pub fn branch(&self) -> Writer {
+        Self {
+            log: Vec::new(),
+            write_fn: self.write_fn,
+            write_err_fn: self.write_err_fn,
         }
     }
 
@@ -20,6 +33,11 @@ impl Writer {
         (self.write_fn)(self, val);
     }
 
+    // When logging, errors are logged along with everything else.
+    pub fn write_err(&mut self, val: String) {
+        (self.write_err_fn)(self, val);
+    }
+
     fn write_log(&mut self, val: String) {
         self.log.push(val);
     }
@@ -27,4 +45,8 @@ impl Writer {
     fn write_stdout(&mut self, val: String) {
         println!("{val}");
     }
+
+    fn write_stderr(&mut self, val: String) {
+        eprintln!("{val}");
+    }
 }